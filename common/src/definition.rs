@@ -0,0 +1,193 @@
+use std::collections::BTreeMap;
+use std::path::{Component, Path};
+
+use serde::{Deserialize, Serialize};
+
+/// What a repository's `.starfish.toml` says to build. Once a build reads it,
+/// it's stored on the build as-is so the build can be repeated later.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BuildDef {
+  #[serde(default)]
+  pub inputs: Vec<InputDef>,
+  // same as the build's systems
+  pub systems: Option<Vec<String>>,
+  pub timeout_secs: Option<i32>,
+  // same as the build's publish
+  pub publish: Option<String>,
+}
+
+/// A single input, as accepted by `PUT /api/build` and `.starfish.toml`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct InputDef {
+  pub path: String,
+  #[serde(default)]
+  pub jobset: bool,
+  // overrides the build's systems
+  pub systems: Option<Vec<String>>,
+  // `-A`, not for flakes
+  pub attr: Option<String>,
+  // `--arg name expr`
+  #[serde(default)]
+  pub args: BTreeMap<String, String>,
+  // `--argstr name value`
+  #[serde(default)]
+  pub argstrs: BTreeMap<String, String>,
+  // `--option name value`, only the allowed ones
+  #[serde(default)]
+  pub options: BTreeMap<String, String>,
+  // only the allowed ones
+  #[serde(default)]
+  pub env: BTreeMap<String, String>,
+}
+
+impl InputDef {
+  /// Turns everything into extra arguments for nix and `NAME=value` pairs for
+  /// the environment, the way they're stored in the database.
+  pub fn nix_args(
+    &self,
+    allowed_options: &[String],
+    allowed_env: &[String],
+  ) -> Result<(Vec<String>, Vec<String>), String> {
+    check_path(&self.path)?;
+    if let Some(attr) = &self.attr {
+      if self.path.contains('#') {
+        return Err("flake inputs take their attribute after the #, not in attr".into());
+      }
+      if attr.trim().is_empty() {
+        return Err("attr can't be empty".into());
+      }
+    }
+    if self.path.contains('#') && !(self.args.is_empty() && self.argstrs.is_empty()) {
+      return Err("flake inputs don't take arguments".into());
+    }
+
+    let mut args = vec![];
+    for (flag, values) in [("--arg", &self.args), ("--argstr", &self.argstrs)] {
+      for (name, value) in values {
+        if !is_nix_identifier(name) {
+          return Err(format!("{name:?} isn't a valid argument name"));
+        }
+        args.extend([flag.to_string(), name.clone(), value.clone()]);
+      }
+    }
+    for (name, value) in &self.options {
+      if !allowed_options.contains(name) {
+        return Err(format!("setting the nix option {name:?} isn't allowed"));
+      }
+      args.extend(["--option".to_string(), name.clone(), value.clone()]);
+    }
+
+    let mut env = vec![];
+    for (name, value) in &self.env {
+      if !allowed_env.contains(name) {
+        return Err(format!(
+          "setting the environment variable {name:?} isn't allowed"
+        ));
+      }
+      env.push(format!("{name}={value}"));
+    }
+
+    Ok((args, env))
+  }
+}
+
+/// Makes sure an input's path (the part before the `#` for flakes) stays
+/// inside the repository.
+pub fn check_path(path: &str) -> Result<(), String> {
+  let flake = path.split_once('#').map(|(flake, _)| flake);
+  let file = flake.unwrap_or(path);
+  if path.trim().is_empty() {
+    return Err("paths can't be empty".into());
+  }
+  // nix would take it as a flag
+  if path.starts_with('-') {
+    return Err(format!("{path:?} can't start with -"));
+  }
+  // to nix, `path:/etc`, `github:owner/repo` and `nixpkgs` are all flakes
+  // from somewhere else
+  if file.contains(':') {
+    return Err(format!("{path:?} can't contain :"));
+  }
+  if flake.map_or(false, |f| {
+    !f.is_empty() && !f.starts_with('.') && !f.contains('/')
+  }) {
+    return Err(format!(
+      "{path:?} would be taken for a flake from the registry, use ./{path} instead"
+    ));
+  }
+  if file.starts_with('~')
+    || Path::new(file)
+      .components()
+      .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+  {
+    return Err(format!(
+      "{path:?} has to be a relative path inside the repository"
+    ));
+  }
+  Ok(())
+}
+
+fn is_nix_identifier(s: &str) -> bool {
+  let mut chars = s.chars();
+  chars
+    .next()
+    .map_or(false, |c| c.is_ascii_alphabetic() || c == '_')
+    && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '\'' | '-'))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn misspelled_input_fields_are_rejected() {
+    let def =
+      |input: &str| serde_json::from_str::<BuildDef>(&format!(r#"{{"inputs": [{input}]}}"#));
+    assert!(def(r#"{"path": "default.nix", "argstrs": {"a": "b"}}"#).is_ok());
+    assert!(def(r#"{"path": "default.nix", "argstr": {"a": "b"}}"#).is_err());
+    assert!(def(r#"{"path": "default.nix", "system": ["x86_64-linux"]}"#).is_err());
+  }
+
+  #[test]
+  fn paths_stay_in_the_repository() {
+    for ok in [
+      "default.nix",
+      "./release.nix",
+      "nix/ci.nix",
+      ".",
+      ".#hello",
+      "#hello",
+      "sub/dir#packages.x86_64-linux.default",
+      "sub/dir#x",
+      "./sub#x",
+      // only the part before the # is a path
+      ".#../not-a-path",
+      "a..b.nix",
+    ] {
+      assert!(check_path(ok).is_ok(), "{ok}");
+    }
+    for bad in [
+      "",
+      "  ",
+      "-",
+      "--expr",
+      "-I/etc",
+      "/etc/passwd",
+      "../other",
+      "nix/../../other",
+      "..#hello",
+      "path:/etc#x",
+      "path:.#x",
+      "git+file:///x#y",
+      "github:owner/repo#x",
+      "nixpkgs#hello",
+      "sub#x",
+      "~/default.nix",
+      "~root",
+    ] {
+      assert!(check_path(bad).is_err(), "{bad}");
+    }
+  }
+}
@@ -7,9 +7,16 @@ use itertools::Itertools;
 use log::{debug, info};
 use serde::{Deserialize, Serialize};
 pub use sqlx::error::BoxDynError;
+use sqlx::types::Json;
 use sqlx::{Executor, FromRow, Postgres};
 
-#[derive(Debug, Serialize, FromRow)]
+pub use crate::definition::{check_path, BuildDef, InputDef};
+pub use crate::queue::NewBuild;
+
+mod definition;
+mod queue;
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
 pub struct Build {
   pub id: i32,
   pub origin: String,
@@ -18,9 +25,40 @@ pub struct Build {
   pub status: BuildStatus,
   pub finished_at: Option<DateTime<Utc>>,
   pub error_msg: Option<String>,
+  pub worker_id: Option<String>,
+  pub priority: i32,
+  pub timeout_secs: Option<i32>,
+  pub failure_kind: Option<FailureKind>,
+  // null means whatever the worker's target_platforms are
+  pub systems: Option<Vec<String>>,
+  // set if the inputs came from the repository's .starfish.toml
+  pub definition: Option<Json<BuildDef>>,
+  pub project_id: Option<i32>,
+  // the branch rev was resolved from, if the build wasn't for a specific commit
+  pub branch: Option<String>,
+  // one of the worker's publish_targets, or "none". null means the worker's own
+  // publish setting
+  pub publish: Option<String>,
+  // whoever the auth proxy said queued it
+  pub submitted_by: Option<String>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct BuildAttempt {
+  pub id: i32,
+  pub build_id: i32,
+  pub attempt: i32,
+  pub status: BuildStatus,
+  pub worker_id: Option<String>,
+  pub started_at: DateTime<Utc>,
+  pub finished_at: Option<DateTime<Utc>>,
+  pub error_msg: Option<String>,
+  pub failure_kind: Option<FailureKind>,
+  // relative to the log directory
+  pub log_file: String,
 }
 
-#[derive(Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct InputOutputs {
   #[serde(flatten)]
   input: Input,
@@ -34,14 +72,31 @@ impl Build {
   {
     sqlx::query_as!(
       Self,
-      "SELECT id, origin, rev, created_at, status as \"status: _\", finished_at, error_msg FROM \
-       builds WHERE id = $1",
+      "SELECT id, origin, rev, created_at, status as \"status: _\", finished_at, error_msg, \
+       worker_id, priority, timeout_secs, failure_kind as \"failure_kind: _\", systems, \
+       definition as \"definition: _\", project_id, branch, publish, submitted_by FROM builds \
+       WHERE id = $1",
       id
     )
     .fetch_optional(executor)
     .await
   }
 
+  pub async fn get_attempts<'e, 'c: 'e, E>(&self, db: E) -> sqlx::Result<Vec<BuildAttempt>>
+  where
+    E: 'e + Executor<'c, Database = Postgres>,
+  {
+    sqlx::query_as!(
+      BuildAttempt,
+      "SELECT id, build_id, attempt, status as \"status: _\", worker_id, started_at, finished_at, \
+       error_msg, failure_kind as \"failure_kind: _\", log_file FROM build_attempts WHERE \
+       build_id = $1 ORDER BY attempt",
+      self.id
+    )
+    .fetch_all(db)
+    .await
+  }
+
   pub async fn get_inputs_and_outputs<'e, 'c: 'e, E>(
     &self,
     db: E,
@@ -55,7 +110,8 @@ impl Build {
 
     let outputs = sqlx::query_as!(
       Output,
-      "SELECT * FROM outputs WHERE input_id = any ($1)",
+      "SELECT id, input_id, system, store_path, drv_path, status as \"status: _\", failure_kind \
+       as \"failure_kind: _\" FROM outputs WHERE input_id = any ($1)",
       &inputs.iter().map(|x| x.id).collect::<Vec<_>>()
     )
     .fetch_all(db)
@@ -78,19 +134,147 @@ impl Build {
   }
 }
 
-#[derive(Debug, Serialize, FromRow)]
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone)]
 pub struct Input {
   pub id: i32,
   pub build_id: i32,
   pub path: String,
+  // gets evaluated into more inputs instead of being built itself
+  pub jobset: bool,
+  // the jobset this input was found in
+  pub parent_id: Option<i32>,
+  // `-A` for the input, or where a job is in its jobset
+  pub attr: Option<String>,
+  // only set for inputs that came from a jobset
+  pub system: Option<String>,
+  // overrides the build's systems
+  pub systems: Option<Vec<String>>,
+  // passed to every nix command for this input, jobs get their jobset's
+  pub args: Vec<String>,
+  // `NAME=value`
+  pub env: Vec<String>,
 }
 
-#[derive(Debug, Serialize, FromRow, Clone)]
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone)]
 pub struct Output {
   pub id: i32,
   pub input_id: i32,
   pub system: String,
-  pub store_path: String,
+  // null until the build is done, or if it failed
+  pub store_path: Option<String>,
+  pub drv_path: Option<String>,
+  pub status: OutputStatus,
+  pub failure_kind: Option<FailureKind>,
+}
+
+// state of a single input × system pair
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "output_status", rename_all = "snake_case")]
+pub enum OutputStatus {
+  Queued,
+  Building,
+  Succeeded,
+  Failed,
+  // never built, either because there was nothing to build for that system or
+  // because the build stopped before getting to it
+  Skipped,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct WorkerInfo {
+  pub id: String,
+  pub started_at: DateTime<Utc>,
+  pub last_heartbeat: DateTime<Utc>,
+  pub max_builds: i32,
+  pub running_builds: i64,
+  pub systems: Vec<String>,
+}
+
+impl WorkerInfo {
+  pub async fn all<'e, 'c: 'e, E>(executor: E) -> sqlx::Result<Vec<Self>>
+  where
+    E: 'e + Executor<'c, Database = Postgres>,
+  {
+    sqlx::query_as!(
+      Self,
+      "SELECT w.id, w.started_at, w.last_heartbeat, w.max_builds, count(b.id) as \
+       \"running_builds!\", w.systems FROM workers w LEFT JOIN builds b ON b.worker_id = w.id AND \
+       b.status IN ($1, $2) GROUP BY w.id ORDER BY w.id",
+      BuildStatus::Building as _,
+      BuildStatus::Uploading as _
+    )
+    .fetch_all(executor)
+    .await
+  }
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct Project {
+  pub id: i32,
+  pub name: String,
+  pub origin: String,
+  pub default_branch: String,
+  // used for builds that don't list their own
+  pub inputs: Json<Vec<InputDef>>,
+  pub systems: Option<Vec<String>>,
+  pub publish: Option<String>,
+  // workers with polling turned on build these whenever their heads move
+  pub poll_branches: Vec<String>,
+  pub created_at: DateTime<Utc>,
+}
+
+impl Project {
+  pub async fn get<'e, 'c: 'e, E>(name: &str, executor: E) -> sqlx::Result<Option<Self>>
+  where
+    E: 'e + Executor<'c, Database = Postgres>,
+  {
+    sqlx::query_as!(
+      Self,
+      "SELECT id, name, origin, default_branch, inputs as \"inputs: _\", systems, publish, \
+       poll_branches, created_at FROM projects WHERE name = $1",
+      name
+    )
+    .fetch_optional(executor)
+    .await
+  }
+}
+
+// the secret never leaves the database
+#[derive(Debug, Serialize, FromRow)]
+pub struct Webhook {
+  pub id: i32,
+  pub url: String,
+  pub signed: bool,
+  pub events: Vec<String>,
+  pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct WebhookDelivery {
+  pub id: i32,
+  pub webhook_id: i32,
+  pub build_id: i32,
+  pub event: String,
+  pub created_at: DateTime<Utc>,
+  pub payload: Option<Json<serde_json::Value>>,
+  pub attempts: i32,
+  pub next_attempt_at: DateTime<Utc>,
+  pub delivered_at: Option<DateTime<Utc>>,
+  // from the latest attempt
+  pub response_code: Option<i32>,
+  pub error: Option<String>,
+}
+
+// something someone did to a build
+#[derive(Debug, Serialize, FromRow)]
+pub struct AuditEntry {
+  pub id: i32,
+  pub at: DateTime<Utc>,
+  pub actor: Option<String>,
+  pub action: String,
+  pub build_id: Option<i32>,
+  pub details: Option<Json<serde_json::Value>>,
 }
 
 #[derive(Debug, Copy, Clone, Serialize, Deserialize, sqlx::Type)]
@@ -106,6 +290,23 @@ pub enum BuildStatus {
   Canceled,
 }
 
+// why a failed build failed
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "failure_kind", rename_all = "snake_case")]
+pub enum FailureKind {
+  Error,
+  TimedOut,
+  // the nix expression didn't evaluate, so nothing got built
+  Evaluation,
+}
+
+impl BuildStatus {
+  pub fn is_finished(self) -> bool {
+    matches!(self, Self::Succeeded | Self::Failed | Self::Canceled)
+  }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Component {
   Web = 0,
@@ -121,6 +322,11 @@ impl Display for Component {
   }
 }
 
+// full commit hashes, anything else is taken to be a branch (or a tag)
+pub fn is_commit_hash(h: &str) -> bool {
+  h.len() == 40 && h.bytes().all(|c| c.is_ascii_hexdigit())
+}
+
 pub static STARFISH_GIT_SHA: &str = env!("VERGEN_GIT_SHA");
 
 static CFG_DEFAULT: [&str; 2] = [
@@ -0,0 +1,72 @@
+use sqlx::PgConnection;
+
+use crate::{Build, InputDef};
+
+/// A build that's been checked and is ready to go in the queue. Everything
+/// that starts builds goes through this, so they all end up looking the same.
+#[derive(Debug)]
+pub struct NewBuild<'a> {
+  pub origin: &'a str,
+  pub rev: &'a str,
+  pub branch: Option<&'a str>,
+  pub priority: i32,
+  pub timeout_secs: Option<i32>,
+  pub systems: Option<&'a [String]>,
+  pub project_id: Option<i32>,
+  pub publish: Option<&'a str>,
+  pub submitted_by: Option<&'a str>,
+  // each with the args and env from `InputDef::nix_args`
+  pub inputs: Vec<(&'a InputDef, Vec<String>, Vec<String>)>,
+}
+
+impl NewBuild<'_> {
+  /// Inserts the build and its inputs, and lets the workers know. Notifications
+  /// only go out once `conn`'s transaction commits, if it's in one.
+  pub async fn enqueue(&self, conn: &mut PgConnection) -> sqlx::Result<Build> {
+    let build = sqlx::query_as!(
+      Build,
+      "INSERT INTO builds (origin, rev, priority, timeout_secs, systems, project_id, branch, \
+       publish, submitted_by) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING id, origin, \
+       rev, created_at, status as \"status: _\", finished_at, error_msg, worker_id, priority, \
+       timeout_secs, failure_kind as \"failure_kind: _\", systems, definition as \"definition: \
+       _\", project_id, branch, publish, submitted_by",
+      self.origin,
+      self.rev,
+      self.priority,
+      self.timeout_secs,
+      self.systems,
+      self.project_id,
+      self.branch,
+      self.publish,
+      self.submitted_by
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    for (input, args, env) in &self.inputs {
+      sqlx::query!(
+        "INSERT INTO inputs (build_id, path, jobset, systems, attr, args, env) VALUES ($1, $2, \
+         $3, $4, $5, $6, $7)",
+        build.id,
+        input.path.trim(),
+        input.jobset,
+        input.systems.as_deref(),
+        input.attr.as_deref(),
+        args,
+        env
+      )
+      .execute(&mut *conn)
+      .await?;
+    }
+
+    sqlx::query!(
+      "SELECT pg_notify($1, $2)",
+      "build_queued",
+      build.id.to_string()
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(build)
+  }
+}
@@ -84,12 +84,71 @@ export type Build = {
   status: BuildStatus;
   finished_at: string | null;
   error_msg: string | null;
+  worker_id: string | null;
+  priority: number;
+  timeout_secs: number | null;
+  failure_kind: FailureKind | null;
+  systems: string[] | null;
+  definition: BuildDef | null;
+  project_id: number | null;
+  branch: string | null;
+  publish: string | null;
+  submitted_by: string | null;
+};
+
+export type BuildDef = {
+  inputs: InputDef[];
+  systems: string[] | null;
+  timeout_secs: number | null;
+  publish: string | null;
+};
+
+export type InputDef = {
+  path: string;
+  jobset: boolean;
+  systems: string[] | null;
+  attr: string | null;
+  args: { [name: string]: string };
+  argstrs: { [name: string]: string };
+  options: { [name: string]: string };
+  env: { [name: string]: string };
+};
+
+export type FailureKind = "error" | "timed_out" | "evaluation";
+
+export const describeFailure = (k: FailureKind) => {
+  switch (k) {
+    case "error":
+      return "failed";
+    case "timed_out":
+      return "timed out";
+    case "evaluation":
+      return "didn't evaluate";
+    default:
+      throw new Error("unreachable");
+  }
+};
+
+export type BuildAttempt = {
+  id: number;
+  build_id: number;
+  attempt: number;
+  status: BuildStatus;
+  worker_id: string | null;
+  started_at: string;
+  finished_at: string | null;
+  error_msg: string | null;
+  failure_kind: FailureKind | null;
+  log_file: string;
 };
 
 type BuildNewProps = {
   origin: string;
   rev: string;
   paths: string;
+  jobsets: string;
+  priority: number;
+  timeout_secs: number | null;
 };
 
 export type BuildNew = RecordOf<BuildNewProps>;
@@ -97,21 +156,41 @@ export const BuildNew: Record.Factory<BuildNewProps> = Record({
   origin: "",
   rev: "main",
   paths: "",
+  jobsets: "",
+  priority: 0,
+  timeout_secs: null,
 });
 
 export type Input = {
   id: number;
   build_id: number;
   path: string;
+  jobset: boolean;
+  parent_id: number | null;
+  attr: string | null;
+  system: string | null;
+  systems: string[] | null;
+  args: string[];
+  env: string[];
 };
 
 export type Output = {
   id: number;
   input_id: number;
   system: string;
-  store_path: string;
+  store_path: string | null;
+  drv_path: string | null;
+  status: OutputStatus;
+  failure_kind: FailureKind | null;
 };
 
+export type OutputStatus =
+  | "queued"
+  | "building"
+  | "succeeded"
+  | "failed"
+  | "skipped";
+
 export type InputOutputs = Input & {
   outputs: Output[];
 };
@@ -121,6 +200,61 @@ export type GetBuild = {
   inputs: InputOutputs[];
 };
 
+export type Project = {
+  id: number;
+  name: string;
+  origin: string;
+  default_branch: string;
+  inputs: InputDef[];
+  systems: string[] | null;
+  publish: string | null;
+  poll_branches: string[];
+  created_at: string;
+};
+
+export type GetProjectBuilds = {
+  project: Project;
+  branches: Build[];
+};
+
+export type WebhookEvent =
+  | "queued"
+  | "started"
+  | "succeeded"
+  | "failed"
+  | "canceled";
+
+export type Webhook = {
+  id: number;
+  url: string;
+  signed: boolean;
+  events: WebhookEvent[];
+  created_at: string;
+};
+
+export type WebhookDelivery = {
+  id: number;
+  webhook_id: number;
+  build_id: number;
+  event: WebhookEvent;
+  created_at: string;
+  payload: { event: WebhookEvent; build: Build; inputs: InputOutputs[] } | null;
+  attempts: number;
+  next_attempt_at: string;
+  delivered_at: string | null;
+  response_code: number | null;
+  error: string | null;
+};
+
+export type AuditEntry = {
+  id: number;
+  at: string;
+  actor: string | null;
+  action: string;
+  build_id: number | null;
+  details: { [key: string]: unknown } | null;
+};
+
 export type Error = {
   code: number;
   reason: string;
@@ -24,11 +24,11 @@ export default function Build({ ...props }: RenderableProps<{ id: string }>) {
 
   const restart = useCallback(() => {
     async function foo() {
-      const response = await api.putJson<{ success: boolean }>(
+      const response = await api.putJson<api.Build>(
         `/api/build/${props.id}/restart`,
         ""
       );
-      if (response.is == "ok" && response.s.success) {
+      if (response.is == "ok") {
         window.location.reload();
       } else {
         alert("Failed to restart build");
@@ -55,9 +55,18 @@ export default function Build({ ...props }: RenderableProps<{ id: string }>) {
           <span class={cx("label", labelclass(build.status))}>
             {build.status}
           </span>
+          {ifn(build.failure_kind, (kind) => (
+            <>
+              {" "}
+              <span class={cx("label", "alert")}>
+                {api.describeFailure(kind)}
+              </span>
+            </>
+          ))}
         </h4>
         <p>
           {build.origin} @ {build.rev}
+          {ifn(build.submitted_by, (who) => ` (queued by ${who})`)}
         </p>
         {ifn(build.error_msg, (msg) => (
           <div class={cx("callout", "alert")}>
@@ -194,16 +203,25 @@ const Outputs = ({ data }: { data: api.InputOutputs[] }) => {
           {data.map((input, i) => (
             <tr key={i}>
               <td>
-                <code>{input.path}</code>
+                <code>
+                  {input.attr == null
+                    ? input.path
+                    : `${input.path} ${input.attr}`}
+                </code>
               </td>
               {input.outputs.length == 0 ? (
-                <td>(not built)</td>
+                <td>{input.jobset ? "(jobset)" : "(not built)"}</td>
               ) : (
                 <td>
                   <ul>
                     {input.outputs.map((output, i) => (
                       <li key={i}>
-                        {output.store_path} - {output.system}
+                        {output.store_path ?? output.drv_path} -{" "}
+                        {output.system} (
+                        {output.failure_kind == null
+                          ? output.status
+                          : api.describeFailure(output.failure_kind)}
+                        )
                       </li>
                     ))}
                   </ul>
@@ -217,7 +235,7 @@ const Outputs = ({ data }: { data: api.InputOutputs[] }) => {
   );
 };
 
-const labelclass = (stat: api.BuildStatus) => {
+export const labelclass = (stat: api.BuildStatus) => {
   switch (stat) {
     case "building":
     case "uploading":
@@ -6,22 +6,97 @@ import { Link } from "preact-router";
 import cx from "../style";
 import NewBuild from "./NewBuild";
 
+const PAGE_SIZE = 20;
+
+const STATUSES = [
+  "queued",
+  "building",
+  "uploading",
+  "succeeded",
+  "failed",
+  "canceled",
+];
+
+interface Filters {
+  status: string;
+  origin: string;
+  rev: string;
+  submitted_by: string;
+}
+
+function buildsUrl(filters: Filters, before?: number): string {
+  const params = new URLSearchParams({ limit: PAGE_SIZE.toString() });
+  for (const [key, value] of Object.entries(filters)) {
+    if (value) {
+      params.set(key, value);
+    }
+  }
+  if (before !== undefined) {
+    params.set("before", before.toString());
+  }
+  return `/api/builds?${params}`;
+}
+
 export default function Home() {
   const [builds, setBuilds] = useState<Build[]>([]);
+  const [more, setMore] = useState(false);
+  const [filters, setFilters] = useState<Filters>({
+    status: "",
+    origin: "",
+    rev: "",
+    submitted_by: "",
+  });
 
-  useEffect(() => {
-    async function dothingy() {
-      const all_builds = await get("/api/builds");
-      const js = await all_builds.json();
-      setBuilds(js);
+  async function load(before?: number) {
+    const response = await get(buildsUrl(filters, before));
+    if (!response.ok) {
+      return;
     }
+    const js: Build[] = await response.json();
+    setBuilds((builds) => (before === undefined ? js : [...builds, ...js]));
+    setMore(js.length == PAGE_SIZE);
+  }
 
-    dothingy();
-  }, [setBuilds]);
+  useEffect(() => {
+    load();
+  }, [filters]);
+
+  function setFilter(key: keyof Filters) {
+    return (e: Event) =>
+      setFilters({
+        ...filters,
+        [key]: (e.target as HTMLInputElement).value.trim(),
+      });
+  }
 
   return (
     <>
       <div id="all-builds" class={cx("cell")}>
+        <div>
+          <select value={filters.status} onChange={setFilter("status")}>
+            <option value="">any status</option>
+            {STATUSES.map((status) => (
+              <option key={status} value={status}>
+                {status}
+              </option>
+            ))}
+          </select>
+          <input
+            placeholder="URL"
+            value={filters.origin}
+            onChange={setFilter("origin")}
+          />
+          <input
+            placeholder="rev"
+            value={filters.rev}
+            onChange={setFilter("rev")}
+          />
+          <input
+            placeholder="queued by"
+            value={filters.submitted_by}
+            onChange={setFilter("submitted_by")}
+          />
+        </div>
         <table>
           <thead>
             <tr>
@@ -32,8 +107,8 @@ export default function Home() {
             </tr>
           </thead>
           <tbody>
-            {builds.map((build, i) => (
-              <tr key={i}>
+            {builds.map((build) => (
+              <tr key={build.id}>
                 <td>
                   <Link href={`/build/${build.id}`}>{build.id}</Link>
                 </td>
@@ -44,6 +119,11 @@ export default function Home() {
             ))}
           </tbody>
         </table>
+        {more && (
+          <button onClick={() => load(builds[builds.length - 1].id)}>
+            Older
+          </button>
+        )}
       </div>
       <div class={cx("cell")}>
         <NewBuild />
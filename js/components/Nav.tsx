@@ -11,6 +11,9 @@ export default function Nav() {
           <li>
             <Link href="/">Home</Link>
           </li>
+          <li>
+            <Link href="/projects">Projects</Link>
+          </li>
         </ul>
       </div>
     </div>
@@ -1,17 +1,24 @@
 import { h } from "preact";
 import { route } from "preact-router";
 import { useCallback, useState } from "preact/hooks";
-import { Build, BuildNew, put } from "../api";
+import { Build, BuildNew, putJson } from "../api";
 import cx from "../style";
 
 export default function NewBuild() {
   const [build, setBuild] = useState(BuildNew());
+  const [error, setError] = useState<string | null>(null);
 
   const submit = useCallback(() => {
     async function dothething() {
-      const resp = await put("/api/build", JSON.stringify(build.toJSON()));
-      const build_json: Build = await resp.json();
-      route(`/build/${build_json.id}`, false);
+      const resp = await putJson<Build>(
+        "/api/build",
+        JSON.stringify(build.toJSON())
+      );
+      if (resp.is == "ok") {
+        route(`/build/${resp.s.id}`, false);
+      } else {
+        setError(resp.s.error.description);
+      }
     }
 
     dothething();
@@ -65,6 +72,21 @@ export default function NewBuild() {
             />
           </label>
         </div>
+        <div class={cx("cell")}>
+          <label>
+            Jobsets to build:{" "}
+            <input
+              name="jobsets"
+              type="text"
+              value={build.jobsets}
+              onInput={(e) =>
+                setBuild((old) =>
+                  old.set("jobsets", (e.target as HTMLInputElement).value)
+                )
+              }
+            />
+          </label>
+        </div>
         <div class={cx("cell")}>
           <button
             class={cx("button")}
@@ -76,6 +98,7 @@ export default function NewBuild() {
             Trigger a build
           </button>
         </div>
+        {error && <div class={cx("cell")}>{error}</div>}
       </div>
     </form>
   );
@@ -0,0 +1,77 @@
+import { Fragment, RenderableProps, h } from "preact";
+import Helmet from "preact-helmet";
+import { Link } from "preact-router";
+import { useEffect, useState } from "preact/hooks";
+import * as api from "../api";
+import cx from "../style";
+import { labelclass } from "./Build";
+
+type ProjectState =
+  | { is: "ok"; s: api.GetProjectBuilds }
+  | { is: "error"; s: { error: api.Error } }
+  | { is: "loading" };
+
+// the latest build of each branch
+export default function Project({
+  ...props
+}: RenderableProps<{ name: string }>) {
+  const [projectState, setProjectState] = useState<ProjectState>({
+    is: "loading",
+  });
+
+  useEffect(() => {
+    async function foo() {
+      setProjectState(
+        await api.getJson<api.GetProjectBuilds>(
+          `/api/projects/${props.name}/builds`
+        )
+      );
+    }
+
+    foo();
+  }, [setProjectState, props.name]);
+
+  if (projectState.is == "loading") {
+    return <div>Loading...</div>;
+  } else if (projectState.is == "error") {
+    return <div>{projectState.s.error.description}</div>;
+  }
+
+  const { project, branches } = projectState.s;
+
+  return (
+    <>
+      <Helmet title={`${project.name} - Starfish`} />
+      <div class={cx("cell")}>
+        <h4>{project.name}</h4>
+        <p>{project.origin}</p>
+        <table>
+          <thead>
+            <tr>
+              <th>Branch</th>
+              <th>Build</th>
+              <th>Rev</th>
+              <th>Status</th>
+            </tr>
+          </thead>
+          <tbody>
+            {branches.map((build) => (
+              <tr key={build.id}>
+                <td>{build.branch ?? "(commits)"}</td>
+                <td>
+                  <Link href={`/build/${build.id}`}>{build.id}</Link>
+                </td>
+                <td>{build.rev}</td>
+                <td>
+                  <span class={cx("label", labelclass(build.status))}>
+                    {build.status}
+                  </span>
+                </td>
+              </tr>
+            ))}
+          </tbody>
+        </table>
+      </div>
+    </>
+  );
+}
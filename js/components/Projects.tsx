@@ -0,0 +1,49 @@
+import { Fragment, h } from "preact";
+import Helmet from "preact-helmet";
+import { Link } from "preact-router";
+import { useEffect, useState } from "preact/hooks";
+import * as api from "../api";
+import cx from "../style";
+
+export default function Projects() {
+  const [projects, setProjects] = useState<api.Project[]>([]);
+
+  useEffect(() => {
+    async function foo() {
+      const response = await api.getJson<api.Project[]>("/api/projects");
+      if (response.is == "ok") {
+        setProjects(response.s);
+      }
+    }
+
+    foo();
+  }, [setProjects]);
+
+  return (
+    <>
+      <Helmet title="Projects - Starfish" />
+      <div class={cx("cell")}>
+        <table>
+          <thead>
+            <tr>
+              <th>Name</th>
+              <th>URL</th>
+              <th>Default branch</th>
+            </tr>
+          </thead>
+          <tbody>
+            {projects.map((project) => (
+              <tr key={project.id}>
+                <td>
+                  <Link href={`/project/${project.name}`}>{project.name}</Link>
+                </td>
+                <td>{project.origin}</td>
+                <td>{project.default_branch}</td>
+              </tr>
+            ))}
+          </tbody>
+        </table>
+      </div>
+    </>
+  );
+}
@@ -6,6 +6,8 @@ import "preact/compat";
 import Nav from "./components/Nav";
 import Home from "./components/Home";
 import Build from "./components/Build";
+import Projects from "./components/Projects";
+import Project from "./components/Project";
 import Footer from "./components/Footer";
 import cx from "./style";
 
@@ -24,6 +26,8 @@ const App = () => {
           <Router>
             <Route path="/" component={Home} />
             <Route path="/build/:id" component={Build} />
+            <Route path="/projects" component={Projects} />
+            <Route path="/project/:name" component={Project} />
             <Route default component={NotFound} />
           </Router>
           <Router>
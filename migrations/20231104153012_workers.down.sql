@@ -0,0 +1,3 @@
+alter table builds drop column worker_id;
+
+drop table workers;
@@ -0,0 +1,7 @@
+create table workers (
+  id varchar(255) primary key,
+  started_at timestamptz not null default now(),
+  last_heartbeat timestamptz not null default now()
+);
+
+alter table builds add column worker_id varchar(255) null references workers(id) on delete set null;
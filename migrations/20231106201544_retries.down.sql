@@ -0,0 +1 @@
+alter table builds drop column retries;
@@ -0,0 +1 @@
+alter table builds add column retries integer not null default 0;
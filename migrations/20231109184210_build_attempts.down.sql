@@ -0,0 +1 @@
+drop table build_attempts;
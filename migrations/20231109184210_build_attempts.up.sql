@@ -0,0 +1,12 @@
+create table build_attempts (
+  id serial primary key,
+  build_id integer not null references builds(id) on delete cascade,
+  attempt integer not null,
+  status build_status not null default 'building',
+  worker_id varchar(255) null references workers(id) on delete set null,
+  started_at timestamptz not null default now(),
+  finished_at timestamptz null,
+  error_msg text null,
+  log_file varchar(512) not null,
+  UNIQUE (build_id, attempt)
+);
@@ -0,0 +1 @@
+alter table workers drop column max_builds;
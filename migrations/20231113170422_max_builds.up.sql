@@ -0,0 +1 @@
+alter table workers add column max_builds integer not null default 0;
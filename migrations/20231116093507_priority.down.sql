@@ -0,0 +1,3 @@
+drop index builds_queue_idx;
+
+alter table builds drop column priority;
@@ -0,0 +1,3 @@
+alter table builds add column priority integer not null default 0;
+
+create index builds_queue_idx on builds (priority desc, created_at, id) where status = 'queued';
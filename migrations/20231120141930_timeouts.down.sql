@@ -0,0 +1,5 @@
+alter table build_attempts drop column failure_kind;
+alter table builds drop column failure_kind;
+alter table builds drop column timeout_secs;
+
+drop type failure_kind;
@@ -0,0 +1,8 @@
+create type failure_kind as enum (
+  'error',
+  'timed_out'
+);
+
+alter table builds add column timeout_secs integer null;
+alter table builds add column failure_kind failure_kind null;
+alter table build_attempts add column failure_kind failure_kind null;
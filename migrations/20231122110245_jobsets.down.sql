@@ -0,0 +1,7 @@
+delete from inputs where parent_id is not null;
+
+alter table inputs drop column failed;
+alter table inputs drop column system;
+alter table inputs drop column attr;
+alter table inputs drop column parent_id;
+alter table inputs drop column jobset;
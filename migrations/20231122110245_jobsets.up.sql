@@ -0,0 +1,7 @@
+-- jobs found by evaluating a jobset input are recorded as inputs of their own,
+-- pointing back at the jobset they came from
+alter table inputs add column jobset boolean not null default false;
+alter table inputs add column parent_id integer null references inputs(id) on delete cascade;
+alter table inputs add column attr text null;
+alter table inputs add column system text null;
+alter table inputs add column failed boolean not null default false;
@@ -0,0 +1,15 @@
+delete from outputs where store_path is null;
+alter table outputs alter column store_path set not null;
+alter table outputs drop column drv_path;
+
+-- enum values can't be dropped, so swap the type out instead
+update builds set failure_kind = 'error' where failure_kind = 'evaluation';
+update build_attempts set failure_kind = 'error' where failure_kind = 'evaluation';
+alter type failure_kind rename to failure_kind_old;
+create type failure_kind as enum (
+  'error',
+  'timed_out'
+);
+alter table builds alter column failure_kind type failure_kind using failure_kind::text::failure_kind;
+alter table build_attempts alter column failure_kind type failure_kind using failure_kind::text::failure_kind;
+drop type failure_kind_old;
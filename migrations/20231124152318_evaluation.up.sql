@@ -0,0 +1,5 @@
+alter type failure_kind add value 'evaluation';
+
+-- outputs get created once the input is evaluated, and filled in once it's built
+alter table outputs add column drv_path text null;
+alter table outputs alter column store_path drop not null;
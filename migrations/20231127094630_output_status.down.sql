@@ -0,0 +1,10 @@
+alter table inputs add column failed boolean not null default false;
+update inputs set failed = true where parent_id is not null and exists (
+  select 1 from outputs where input_id = inputs.id and status = 'failed'
+);
+
+delete from outputs where status <> 'succeeded' and drv_path is null;
+alter table outputs drop column failure_kind;
+alter table outputs drop column status;
+
+drop type output_status;
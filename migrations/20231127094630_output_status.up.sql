@@ -0,0 +1,19 @@
+create type output_status as enum (
+  'queued',
+  'building',
+  'succeeded',
+  'failed',
+  'skipped'
+);
+
+alter table outputs add column status output_status not null default 'queued';
+alter table outputs add column failure_kind failure_kind null;
+
+-- before this, a pair only got a store path once it was built
+update outputs set status = 'succeeded' where store_path is not null;
+update outputs set status = 'failed', failure_kind = 'error' where store_path is null;
+insert into outputs (input_id, system, status, failure_kind)
+  select id, system, 'failed', 'error' from inputs
+  where failed and system is not null and not exists (select 1 from outputs where input_id = inputs.id);
+
+alter table inputs drop column failed;
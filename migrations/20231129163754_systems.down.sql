@@ -0,0 +1,3 @@
+alter table workers drop column systems;
+alter table inputs drop column systems;
+alter table builds drop column systems;
@@ -0,0 +1,6 @@
+-- null means the worker's target_platforms
+alter table builds add column systems text[] null;
+alter table inputs add column systems text[] null;
+
+-- everything the worker's builders can build for
+alter table workers add column systems text[] not null default '{}';
@@ -0,0 +1,2 @@
+alter table inputs drop column env;
+alter table inputs drop column args;
@@ -0,0 +1,4 @@
+-- extra arguments for nix (--arg, --argstr, --option) and `NAME=value`
+-- environment variables, already validated by the web server
+alter table inputs add column args text[] not null default '{}';
+alter table inputs add column env text[] not null default '{}';
@@ -0,0 +1,2 @@
+-- the .starfish.toml a build's inputs came from, if any
+alter table builds add column definition jsonb null;
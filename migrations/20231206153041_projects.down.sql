@@ -0,0 +1,5 @@
+drop index builds_project_branch;
+alter table builds drop column publish;
+alter table builds drop column branch;
+alter table builds drop column project_id;
+drop table projects;
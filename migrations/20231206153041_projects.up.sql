@@ -0,0 +1,19 @@
+create table projects (
+  id serial primary key,
+  name text not null unique,
+  origin text not null,
+  default_branch text not null default 'main',
+  -- used for builds that don't list their own, same format as in .starfish.toml
+  inputs jsonb not null default '[]',
+  systems text[] null,
+  publish text null,
+  created_at timestamptz not null default now()
+);
+
+alter table builds add column project_id int null references projects (id) on delete set null;
+-- null if the build was for a specific commit
+alter table builds add column branch text null;
+-- one of the worker's publish_targets, or 'none'
+alter table builds add column publish text null;
+
+create index builds_project_branch on builds (project_id, branch, created_at desc);
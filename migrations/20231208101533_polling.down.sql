@@ -0,0 +1,2 @@
+drop table branch_heads;
+alter table projects drop column poll_branches;
@@ -0,0 +1,10 @@
+alter table projects add column poll_branches text[] not null default '{}';
+
+-- the last commit the poller saw on each branch it watches
+create table branch_heads (
+  origin text not null,
+  branch text not null,
+  rev text not null,
+  updated_at timestamptz not null default now(),
+  primary key (origin, branch)
+);
@@ -0,0 +1,5 @@
+drop trigger builds_status_report_update on builds;
+drop trigger builds_status_report_insert on builds;
+drop function queue_status_report;
+drop table status_report_attempts;
+drop table status_reports;
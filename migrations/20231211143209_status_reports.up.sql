@@ -0,0 +1,39 @@
+-- one row per status a build goes through, for reporting to the forge. the
+-- notifier only sends the latest one for each build, once the commit is known
+create table status_reports (
+  id serial primary key,
+  build_id int not null references builds (id) on delete cascade,
+  status build_status not null,
+  created_at timestamptz not null default now(),
+  attempts int not null default 0,
+  next_attempt_at timestamptz not null default now(),
+  delivered_at timestamptz null
+);
+
+create index status_reports_build on status_reports (build_id);
+create index status_reports_pending on status_reports (next_attempt_at) where delivered_at is null;
+
+create table status_report_attempts (
+  id serial primary key,
+  report_id int not null references status_reports (id) on delete cascade,
+  attempted_at timestamptz not null default now(),
+  -- null if there was no response at all
+  response_code int null,
+  error text null
+);
+
+create function queue_status_report() returns trigger as $$
+begin
+  insert into status_reports (build_id, status) values (new.id, new.status);
+  perform pg_notify('status_report_queued', new.id::text);
+  return null;
+end;
+$$ language plpgsql;
+
+create trigger builds_status_report_insert after insert on builds
+  for each row execute function queue_status_report();
+
+-- the rev changes once a branch is resolved to a commit
+create trigger builds_status_report_update after update of status, rev on builds
+  for each row when (old.status is distinct from new.status or old.rev is distinct from new.rev)
+  execute function queue_status_report();
@@ -0,0 +1,6 @@
+drop trigger builds_webhook_update on builds;
+drop trigger builds_webhook_insert on builds;
+drop function queue_webhook_deliveries;
+drop table webhook_delivery_attempts;
+drop table webhook_deliveries;
+drop table webhooks;
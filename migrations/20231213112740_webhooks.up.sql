@@ -0,0 +1,62 @@
+create table webhooks (
+  id serial primary key,
+  url text not null,
+  -- signs the payload, if set
+  secret text null,
+  -- any of queued, started, succeeded, failed, canceled
+  events text[] not null,
+  created_at timestamptz not null default now()
+);
+
+create table webhook_deliveries (
+  id serial primary key,
+  webhook_id int not null references webhooks (id) on delete cascade,
+  build_id int not null references builds (id) on delete cascade,
+  event text not null,
+  created_at timestamptz not null default now(),
+  -- filled in on the first attempt, and sent as-is after that
+  payload jsonb null,
+  attempts int not null default 0,
+  next_attempt_at timestamptz not null default now(),
+  delivered_at timestamptz null
+);
+
+create index webhook_deliveries_webhook on webhook_deliveries (webhook_id, created_at desc);
+create index webhook_deliveries_pending on webhook_deliveries (next_attempt_at) where delivered_at is null;
+
+create table webhook_delivery_attempts (
+  id serial primary key,
+  delivery_id int not null references webhook_deliveries (id) on delete cascade,
+  attempted_at timestamptz not null default now(),
+  -- null if there was no response at all
+  response_code int null,
+  error text null
+);
+
+create function queue_webhook_deliveries() returns trigger as $$
+declare
+  ev text := case new.status
+    when 'queued' then 'queued'
+    when 'building' then 'started'
+    when 'succeeded' then 'succeeded'
+    when 'failed' then 'failed'
+    when 'canceled' then 'canceled'
+  end;
+begin
+  if ev is not null then
+    insert into webhook_deliveries (webhook_id, build_id, event)
+      select id, new.id, ev from webhooks where ev = any(events);
+    if found then
+      perform pg_notify('webhook_delivery_queued', new.id::text);
+    end if;
+  end if;
+  return null;
+end;
+$$ language plpgsql;
+
+create trigger builds_webhook_insert after insert on builds
+  for each row execute function queue_webhook_deliveries();
+
+create trigger builds_webhook_update after update of status on builds
+  for each row when (old.status is distinct from new.status)
+  execute function queue_webhook_deliveries();
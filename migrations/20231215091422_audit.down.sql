@@ -0,0 +1,2 @@
+drop table audit_log;
+alter table builds drop column submitted_by;
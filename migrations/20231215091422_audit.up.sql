@@ -0,0 +1,15 @@
+-- whoever the auth proxy said queued the build, if anyone
+alter table builds add column submitted_by text null;
+
+create table audit_log (
+  id serial primary key,
+  at timestamptz not null default now(),
+  -- null if the request didn't come through a trusted proxy
+  actor text null,
+  action text not null,
+  build_id int null references builds (id) on delete set null,
+  details jsonb null
+);
+
+create index audit_log_actor on audit_log (actor, at desc);
+create index audit_log_build on audit_log (build_id);
@@ -0,0 +1,5 @@
+drop index builds_rev;
+drop index builds_submitted_by_created;
+drop index builds_origin_created;
+drop index builds_status_created;
+drop index builds_created;
@@ -0,0 +1,7 @@
+-- for paging through and filtering the builds list
+create index builds_created on builds (created_at desc, id desc);
+create index builds_status_created on builds (status, created_at desc);
+create index builds_origin_created on builds (origin, created_at desc);
+create index builds_submitted_by_created on builds (submitted_by, created_at desc);
+-- rev prefix searches
+create index builds_rev on builds (rev text_pattern_ops);
@@ -0,0 +1,32 @@
+drop trigger builds_webhook_update on builds;
+drop trigger builds_webhook_insert on builds;
+
+create or replace function queue_webhook_deliveries() returns trigger as $$
+declare
+  ev text := case new.status
+    when 'queued' then 'queued'
+    when 'building' then 'started'
+    when 'succeeded' then 'succeeded'
+    when 'failed' then 'failed'
+    when 'canceled' then 'canceled'
+  end;
+begin
+  if ev is not null then
+    insert into webhook_deliveries (webhook_id, build_id, event)
+      select id, new.id, ev from webhooks where ev = any(events);
+    if found then
+      perform pg_notify('webhook_delivery_queued', new.id::text);
+    end if;
+  end if;
+  return null;
+end;
+$$ language plpgsql;
+
+create trigger builds_webhook_insert after insert on builds
+  for each row execute function queue_webhook_deliveries();
+
+create trigger builds_webhook_update after update of status on builds
+  for each row when (old.status is distinct from new.status)
+  execute function queue_webhook_deliveries();
+
+alter table webhook_deliveries drop column snapshot;
@@ -0,0 +1,48 @@
+-- the build and its inputs as they were when the event happened, so late or
+-- retried deliveries don't describe a later state of the build
+alter table webhook_deliveries add column snapshot jsonb null;
+
+create or replace function queue_webhook_deliveries() returns trigger as $$
+declare
+  ev text := case new.status
+    when 'queued' then 'queued'
+    when 'building' then 'started'
+    when 'succeeded' then 'succeeded'
+    when 'failed' then 'failed'
+    when 'canceled' then 'canceled'
+  end;
+begin
+  if ev is not null then
+    insert into webhook_deliveries (webhook_id, build_id, event, snapshot)
+      select id, new.id, ev, jsonb_build_object(
+        'build', to_jsonb(new),
+        'inputs', (
+          select coalesce(jsonb_agg(to_jsonb(i) || jsonb_build_object('outputs', (
+            select coalesce(jsonb_agg(to_jsonb(o) order by o.id), '[]')
+            from outputs o where o.input_id = i.id
+          )) order by i.id), '[]')
+          from inputs i where i.build_id = new.id
+        )
+      )
+      from webhooks where ev = any(events);
+    if found then
+      perform pg_notify('webhook_delivery_queued', new.id::text);
+    end if;
+  end if;
+  return null;
+end;
+$$ language plpgsql;
+
+-- deferred to the end of the transaction, since a new build's inputs are
+-- inserted after the build itself
+drop trigger builds_webhook_insert on builds;
+drop trigger builds_webhook_update on builds;
+
+create constraint trigger builds_webhook_insert after insert on builds
+  deferrable initially deferred
+  for each row execute function queue_webhook_deliveries();
+
+create constraint trigger builds_webhook_update after update of status on builds
+  deferrable initially deferred
+  for each row when (old.status is distinct from new.status)
+  execute function queue_webhook_deliveries();
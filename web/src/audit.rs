@@ -0,0 +1,98 @@
+use std::future::{ready, Ready};
+
+use actix_web::dev::Payload;
+use actix_web::{get, web, FromRequest, HttpRequest, Responder};
+use common::AuditEntry;
+use serde::Deserialize;
+use sqlx::types::Json;
+use sqlx::{Executor, PgPool, Postgres};
+
+use crate::error::ApiError;
+use crate::Config;
+
+/// Who's making the request, according to the auth proxy in front of us.
+/// Anyone else can't say.
+#[derive(Debug, Clone)]
+pub struct Identity(pub Option<String>);
+
+impl FromRequest for Identity {
+  type Error = actix_web::Error;
+  type Future = Ready<Result<Self, Self::Error>>;
+
+  fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+    let cfg = req
+      .app_data::<web::Data<Config>>()
+      .expect("the config is always there");
+    ready(Ok(Self(identify(cfg, req))))
+  }
+}
+
+fn identify(cfg: &Config, req: &HttpRequest) -> Option<String> {
+  let header = cfg.identity_header.as_ref()?;
+  let peer = req.peer_addr()?.ip();
+  if !cfg.trusted_proxies.contains(&peer) {
+    return None;
+  }
+  req
+    .headers()
+    .get(header)
+    .and_then(|v| v.to_str().ok())
+    .map(str::trim)
+    .filter(|v| !v.is_empty())
+    .map(String::from)
+}
+
+pub(crate) async fn record<'c, E>(
+  executor: E,
+  identity: &Identity,
+  action: &str,
+  build_id: Option<i32>,
+  details: Option<serde_json::Value>,
+) -> Result<(), ApiError>
+where
+  E: Executor<'c, Database = Postgres>,
+{
+  sqlx::query!(
+    "INSERT INTO audit_log (actor, action, build_id, details) VALUES ($1, $2, $3, $4)",
+    identity.0.as_deref(),
+    action,
+    build_id,
+    details.map(Json) as _
+  )
+  .execute(executor)
+  .await?;
+  Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditQuery {
+  actor: Option<String>,
+  action: Option<String>,
+  build_id: Option<i32>,
+  // entries older than this one, for paging
+  before: Option<i32>,
+  limit: Option<i64>,
+}
+
+// newest first
+#[get("audit")]
+pub(crate) async fn get_audit(
+  db: web::Data<PgPool>,
+  query: web::Query<AuditQuery>,
+) -> Result<impl Responder, ApiError> {
+  Ok(web::Json(
+    sqlx::query_as!(
+      AuditEntry,
+      "SELECT id, at, actor, action, build_id, details as \"details: _\" FROM audit_log WHERE \
+       ($1::text IS NULL OR actor = $1) AND ($2::text IS NULL OR action = $2) AND ($3::int IS \
+       NULL OR build_id = $3) AND ($4::int IS NULL OR id < $4) ORDER BY id DESC LIMIT $5",
+      query.actor.as_deref(),
+      query.action.as_deref(),
+      query.build_id,
+      query.before,
+      query.limit.unwrap_or(100).clamp(1, 1000)
+    )
+    .fetch_all(&**db)
+    .await?,
+  ))
+}
@@ -0,0 +1,77 @@
+use actix_files::NamedFile;
+use actix_web::http::header::ContentType;
+use actix_web::{get, web, HttpResponse, Responder};
+
+use crate::error::ApiError;
+use crate::Config;
+
+// nix's own base32, which leaves out e, o, u and t. store paths use 32 of them
+// and nar files 52
+fn is_nix_hash(s: &str, len: usize) -> bool {
+  s.len() == len
+    && s
+      .bytes()
+      .all(|c| matches!(c, b'0'..=b'9' | b'a'..=b'd' | b'f'..=b'n' | b'p'..=b's' | b'v'..=b'z'))
+}
+
+async fn serve(cfg: &Config, file: &str, content_type: &str) -> Result<NamedFile, ApiError> {
+  let Some(dir) = &cfg.binary_cache else {
+    return Err(ApiError::NotFound("binary cache"))
+  };
+  Ok(
+    NamedFile::open_async(dir.join(file))
+      .await
+      .map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => ApiError::NotFound("path"),
+        _ => e.into(),
+      })?
+      .set_content_type(content_type.parse().expect("content types are valid")),
+  )
+}
+
+// the workers' `nix copy` writes one of these too, but it's not there until the
+// first build is published
+#[get("/nix-cache-info")]
+pub(crate) async fn get_cache_info(cfg: web::Data<Config>) -> Result<impl Responder, ApiError> {
+  if cfg.binary_cache.is_none() {
+    return Err(ApiError::NotFound("binary cache"));
+  }
+  Ok(
+    HttpResponse::Ok()
+      .insert_header(ContentType(
+        "text/x-nix-cache-info"
+          .parse()
+          .expect("content types are valid"),
+      ))
+      .body("StoreDir: /nix/store\nWantMassQuery: 1\nPriority: 40\n"),
+  )
+}
+
+#[get("/{hash}.narinfo")]
+pub(crate) async fn get_narinfo(
+  cfg: web::Data<Config>,
+  hash: web::Path<String>,
+) -> Result<impl Responder, ApiError> {
+  if !is_nix_hash(&hash, 32) {
+    return Err(ApiError::NotFound("path"));
+  }
+  serve(&cfg, &format!("{hash}.narinfo"), "text/x-nix-narinfo").await
+}
+
+// like `nar/<hash>.nar.xz`, the narinfo says which
+#[get("/nar/{file}")]
+pub(crate) async fn get_nar(
+  cfg: web::Data<Config>,
+  file: web::Path<String>,
+) -> Result<impl Responder, ApiError> {
+  let valid = file.split_once('.').map_or(false, |(hash, ext)| {
+    is_nix_hash(hash, 52)
+      && ext
+        .split('.')
+        .all(|e| !e.is_empty() && e.bytes().all(|c| c.is_ascii_alphanumeric()))
+  });
+  if !valid {
+    return Err(ApiError::NotFound("path"));
+  }
+  serve(&cfg, &format!("nar/{file}"), "application/x-nix-nar").await
+}
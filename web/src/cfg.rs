@@ -11,13 +11,48 @@ pub struct Config {
   pub database_url: String,
   pub listen_address: String,
   pub listen_port: u16,
+
+  // nix options (`--option name value`) builds may set on their inputs. anything
+  // else is rejected, since options like post-build-hook can run arbitrary
+  // commands on the worker
+  #[serde(default)]
+  pub allowed_options: Vec<String>,
+  // same thing, for environment variables passed to nix
+  #[serde(default)]
+  pub allowed_env: Vec<String>,
+  // what repository urls builds and projects can use. `user@host:path` counts
+  // as ssh, and plain paths as file
+  #[serde(default = "default_origin_schemes")]
+  pub origin_schemes: Vec<String>,
+
+  // header an authenticating proxy puts the user's name in. it's only believed
+  // coming from one of trusted_proxies, anyone else could set it themselves
+  pub identity_header: Option<String>,
+  #[serde(default)]
+  pub trusted_proxies: Vec<IpAddr>,
+
+  // a file:// binary cache the workers publish to, served at /nix-cache-info,
+  // /*.narinfo and /nar/*
+  pub binary_cache: Option<PathBuf>,
+
+  // each forge's webhook under /api/hooks is turned off until it has a secret
+  #[serde(default)]
+  pub hooks: HookSecrets,
 }
 
-impl Config {
-  pub fn logfile(&self, id: i32) -> PathBuf {
-    self.log_path.join(format!("{id}.log"))
-  }
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct HookSecrets {
+  pub github: Option<String>,
+  pub gitea: Option<String>,
+  // gitlab sends this back as-is instead of signing with it
+  pub gitlab: Option<String>,
+}
 
+fn default_origin_schemes() -> Vec<String> {
+  vec!["https".into(), "http".into(), "ssh".into(), "git".into()]
+}
+
+impl Config {
   pub fn listen_addr(&self) -> Result<SocketAddr, <IpAddr as FromStr>::Err> {
     Ok(SocketAddr::from((
       self.listen_address.parse::<IpAddr>()?,
@@ -0,0 +1,100 @@
+use std::fmt;
+
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use log::error;
+use serde_json::json;
+
+/// Everything the api answers with when it can't do what was asked. They all
+/// go out as `{"error": {code, reason, description}}`.
+#[derive(Debug)]
+pub enum ApiError {
+  // what there isn't, like "build"
+  NotFound(&'static str),
+  BadRequest(String),
+  Unauthorized(String),
+  // the request made sense but can't be done as is. the reason is short and
+  // machine readable, like `invalid_origin`
+  Invalid {
+    reason: &'static str,
+    description: String,
+  },
+  Conflict(String),
+  // only logged, since it can have queries and whatnot in it
+  Internal(anyhow::Error),
+}
+
+impl ApiError {
+  pub fn invalid(reason: &'static str, description: impl Into<String>) -> Self {
+    Self::Invalid {
+      reason,
+      description: description.into(),
+    }
+  }
+
+  fn reason(&self) -> &'static str {
+    match self {
+      Self::NotFound(_) => "not_found",
+      Self::BadRequest(_) => "bad_request",
+      Self::Unauthorized(_) => "unauthorized",
+      Self::Invalid { reason, .. } => reason,
+      Self::Conflict(_) => "conflict",
+      Self::Internal(_) => "internal",
+    }
+  }
+}
+
+impl fmt::Display for ApiError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::NotFound(what) => write!(f, "there's no {what} like that"),
+      Self::BadRequest(s) | Self::Unauthorized(s) | Self::Conflict(s) => f.write_str(s),
+      Self::Invalid { description, .. } => f.write_str(description),
+      Self::Internal(_) => f.write_str("something went wrong, the server's log has more"),
+    }
+  }
+}
+
+impl ResponseError for ApiError {
+  fn status_code(&self) -> StatusCode {
+    match self {
+      Self::NotFound(_) => StatusCode::NOT_FOUND,
+      Self::BadRequest(_) => StatusCode::BAD_REQUEST,
+      Self::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+      Self::Invalid { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+      Self::Conflict(_) => StatusCode::CONFLICT,
+      Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+  }
+
+  fn error_response(&self) -> HttpResponse {
+    if let Self::Internal(e) = self {
+      error!("{:?}", e);
+    }
+    HttpResponse::build(self.status_code()).json(json!({
+      "error": {
+        "code": self.status_code().as_u16(),
+        "reason": self.reason(),
+        "description": self.to_string(),
+      }
+    }))
+  }
+}
+
+impl From<sqlx::Error> for ApiError {
+  fn from(e: sqlx::Error) -> Self {
+    Self::Internal(e.into())
+  }
+}
+
+impl From<std::io::Error> for ApiError {
+  fn from(e: std::io::Error) -> Self {
+    Self::Internal(e.into())
+  }
+}
+
+// for the extractors, so a body or query that doesn't parse gets the same kind
+// of answer as everything else
+pub(crate) fn bad_request<E: fmt::Display>(e: E, _: &actix_web::HttpRequest) -> actix_web::Error {
+  ApiError::BadRequest(e.to_string()).into()
+}
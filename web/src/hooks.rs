@@ -0,0 +1,559 @@
+use actix_web::{post, web, HttpRequest, Responder};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use serde_json::json;
+use sha2::Sha256;
+use sqlx::PgPool;
+use subtle::ConstantTimeEq;
+
+use crate::error::ApiError;
+use crate::{queue_build, BuildPlsNew, Config};
+
+// github caps deliveries at this size
+pub(crate) const MAX_PAYLOAD: usize = 25 * 1024 * 1024;
+
+/// A commit a forge wants built.
+#[derive(Debug, PartialEq, Eq)]
+pub struct HookBuild {
+  // every url the repository goes by, the first one is built from if it isn't
+  // a project's
+  pub origins: Vec<String>,
+  pub rev: String,
+  // unset for pull requests
+  pub branch: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HookOpts {
+  // otherwise the project is found by the repository's url
+  project: Option<String>,
+}
+
+// github and gitea send the same thing, give or take
+#[derive(Deserialize)]
+struct Repository {
+  clone_url: String,
+  ssh_url: String,
+}
+
+#[derive(Deserialize)]
+struct Push {
+  #[serde(rename = "ref")]
+  ref_: String,
+  after: String,
+  repository: Repository,
+}
+
+#[derive(Deserialize)]
+struct PullRequestEvent {
+  action: String,
+  pull_request: PullRequest,
+}
+
+#[derive(Deserialize)]
+struct PullRequest {
+  head: PullRequestHead,
+}
+
+#[derive(Deserialize)]
+struct PullRequestHead {
+  sha: String,
+  // null if the fork it came from is gone
+  repo: Option<Repository>,
+}
+
+#[derive(Deserialize)]
+struct GitlabProject {
+  git_http_url: String,
+  git_ssh_url: String,
+}
+
+#[derive(Deserialize)]
+struct GitlabPush {
+  #[serde(rename = "ref")]
+  ref_: String,
+  after: String,
+  project: GitlabProject,
+}
+
+#[derive(Deserialize)]
+struct GitlabMergeRequestEvent {
+  object_attributes: GitlabMergeRequest,
+}
+
+#[derive(Deserialize)]
+struct GitlabMergeRequest {
+  action: Option<String>,
+  // only set on updates that added commits
+  oldrev: Option<String>,
+  last_commit: GitlabCommit,
+  source: GitlabProject,
+}
+
+#[derive(Deserialize)]
+struct GitlabCommit {
+  id: String,
+}
+
+// pushes of tags and deleted branches aren't built
+fn push(ref_: &str, after: String, origins: Vec<String>) -> Option<HookBuild> {
+  let branch = ref_.strip_prefix("refs/heads/")?;
+  if after.bytes().all(|c| c == b'0') {
+    return None;
+  }
+  Some(HookBuild {
+    origins,
+    rev: after,
+    branch: Some(branch.to_string()),
+  })
+}
+
+fn pull_request(event: PullRequestEvent, actions: &[&str]) -> Option<HookBuild> {
+  if !actions.contains(&event.action.as_str()) {
+    return None;
+  }
+  let head = event.pull_request.head;
+  let repo = head.repo?;
+  Some(HookBuild {
+    origins: vec![repo.clone_url, repo.ssh_url],
+    rev: head.sha,
+    branch: None,
+  })
+}
+
+/// Figures out what to build for a GitHub event, from its `X-GitHub-Event`
+/// header and body. `None` means there's nothing to build.
+pub fn parse_github(event: &str, body: &[u8]) -> serde_json::Result<Option<HookBuild>> {
+  parse_github_like(event, body, "synchronize")
+}
+
+/// Same as `parse_github`, for the `X-Gitea-Event` header.
+pub fn parse_gitea(event: &str, body: &[u8]) -> serde_json::Result<Option<HookBuild>> {
+  parse_github_like(event, body, "synchronized")
+}
+
+// `synchronize` is what a pull request getting new commits is called
+fn parse_github_like(
+  event: &str,
+  body: &[u8],
+  synchronize: &str,
+) -> serde_json::Result<Option<HookBuild>> {
+  Ok(match event {
+    "push" => {
+      let p: Push = serde_json::from_slice(body)?;
+      let origins = vec![p.repository.clone_url, p.repository.ssh_url];
+      push(&p.ref_, p.after, origins)
+    }
+    "pull_request" => pull_request(
+      serde_json::from_slice(body)?,
+      &["opened", synchronize, "reopened"],
+    ),
+    _ => None,
+  })
+}
+
+/// Same as `parse_github`, for the `X-Gitlab-Event` header.
+pub fn parse_gitlab(event: &str, body: &[u8]) -> serde_json::Result<Option<HookBuild>> {
+  Ok(match event {
+    "Push Hook" => {
+      let p: GitlabPush = serde_json::from_slice(body)?;
+      let origins = vec![p.project.git_http_url, p.project.git_ssh_url];
+      push(&p.ref_, p.after, origins)
+    }
+    "Merge Request Hook" => {
+      let mr = serde_json::from_slice::<GitlabMergeRequestEvent>(body)?.object_attributes;
+      match mr.action.as_deref() {
+        Some("open" | "reopen") => {}
+        Some("update") if mr.oldrev.is_some() => {}
+        _ => return Ok(None),
+      }
+      Some(HookBuild {
+        origins: vec![mr.source.git_http_url, mr.source.git_ssh_url],
+        rev: mr.last_commit.id,
+        branch: None,
+      })
+    }
+    _ => None,
+  })
+}
+
+/// Checks a GitHub or Gitea signature, the hex HMAC-SHA256 of the body.
+pub fn is_signed(secret: &str, body: &[u8], signature: &str) -> bool {
+  let Ok(signature) = hex::decode(signature) else {
+    return false
+  };
+  let mut mac =
+    Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac takes keys of any size");
+  mac.update(body);
+  mac.verify_slice(&signature).is_ok()
+}
+
+fn header<'a>(req: &'a HttpRequest, name: &str) -> Option<&'a str> {
+  req.headers().get(name).and_then(|v| v.to_str().ok())
+}
+
+async fn queue(
+  cfg: &Config,
+  db: &PgPool,
+  opts: &HookOpts,
+  hook: Option<HookBuild>,
+) -> Result<serde_json::Value, ApiError> {
+  let Some(hook) = hook else {
+    return Ok(json!({ "queued": false }))
+  };
+
+  let project = match &opts.project {
+    Some(name) => Some(name.clone()),
+    None => {
+      sqlx::query_scalar!(
+        "SELECT name FROM projects WHERE origin = any($1) ORDER BY id LIMIT 1",
+        &hook.origins
+      )
+      .fetch_optional(db)
+      .await?
+    }
+  };
+
+  // forges retry deliveries, and the same commit can be pushed to several
+  // branches
+  let exists = sqlx::query_scalar!(
+    "SELECT EXISTS (SELECT 1 FROM builds WHERE rev = $2 AND (origin = any($1) OR project_id = \
+     (SELECT id FROM projects WHERE name = $3))) as \"exists!\"",
+    &hook.origins,
+    &hook.rev,
+    project.as_deref()
+  )
+  .fetch_one(db)
+  .await?;
+  if exists {
+    return Ok(json!({ "queued": false }));
+  }
+
+  let build = queue_build(
+    cfg,
+    db,
+    &BuildPlsNew {
+      origin: project.is_none().then(|| hook.origins[0].clone()),
+      project,
+      rev: Some(hook.rev),
+      branch: hook.branch,
+      ..Default::default()
+    },
+    None,
+  )
+  .await?;
+
+  Ok(json!({ "queued": true, "build": build }))
+}
+
+#[post("hooks/github")]
+pub(crate) async fn post_github(
+  cfg: web::Data<Config>,
+  db: web::Data<PgPool>,
+  req: HttpRequest,
+  opts: web::Query<HookOpts>,
+  body: web::Bytes,
+) -> Result<impl Responder, ApiError> {
+  let Some(secret) = &cfg.hooks.github else {
+    return Err(ApiError::NotFound("hook"))
+  };
+  let signature = header(&req, "X-Hub-Signature-256").and_then(|s| s.strip_prefix("sha256="));
+  if !signature.map_or(false, |s| is_signed(secret, &body, s)) {
+    return Err(ApiError::Unauthorized("bad signature".into()));
+  }
+
+  let hook = parse_github(header(&req, "X-GitHub-Event").unwrap_or_default(), &body)
+    .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+  Ok(web::Json(queue(&cfg, &db, &opts, hook).await?))
+}
+
+#[post("hooks/gitea")]
+pub(crate) async fn post_gitea(
+  cfg: web::Data<Config>,
+  db: web::Data<PgPool>,
+  req: HttpRequest,
+  opts: web::Query<HookOpts>,
+  body: web::Bytes,
+) -> Result<impl Responder, ApiError> {
+  let Some(secret) = &cfg.hooks.gitea else {
+    return Err(ApiError::NotFound("hook"))
+  };
+  let signature = header(&req, "X-Gitea-Signature");
+  if !signature.map_or(false, |s| is_signed(secret, &body, s)) {
+    return Err(ApiError::Unauthorized("bad signature".into()));
+  }
+
+  let hook = parse_gitea(header(&req, "X-Gitea-Event").unwrap_or_default(), &body)
+    .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+  Ok(web::Json(queue(&cfg, &db, &opts, hook).await?))
+}
+
+#[post("hooks/gitlab")]
+pub(crate) async fn post_gitlab(
+  cfg: web::Data<Config>,
+  db: web::Data<PgPool>,
+  req: HttpRequest,
+  opts: web::Query<HookOpts>,
+  body: web::Bytes,
+) -> Result<impl Responder, ApiError> {
+  let Some(secret) = &cfg.hooks.gitlab else {
+    return Err(ApiError::NotFound("hook"))
+  };
+  let token = header(&req, "X-Gitlab-Token").unwrap_or_default();
+  if !bool::from(token.as_bytes().ct_eq(secret.as_bytes())) {
+    return Err(ApiError::Unauthorized("bad token".into()));
+  }
+
+  let hook = parse_gitlab(header(&req, "X-Gitlab-Event").unwrap_or_default(), &body)
+    .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+  Ok(web::Json(queue(&cfg, &db, &opts, hook).await?))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // trimmed down from real deliveries, with some of the fields we don't read
+  // left in
+  const GITHUB_PUSH: &str = r#"{
+    "ref": "refs/heads/main",
+    "before": "6113728f27ae82c7b1a177c8d03f9e96e0adf246",
+    "after": "0d1a26e67d8f5eaf1f6ba5c57fc3c7d91ac0fd1c",
+    "created": false,
+    "deleted": false,
+    "repository": {
+      "id": 186853002,
+      "full_name": "octocat/hello-world",
+      "clone_url": "https://github.com/octocat/hello-world.git",
+      "ssh_url": "git@github.com:octocat/hello-world.git",
+      "default_branch": "main"
+    },
+    "pusher": { "name": "octocat", "email": "octocat@github.com" }
+  }"#;
+
+  const GITHUB_PULL_REQUEST: &str = r#"{
+    "action": "opened",
+    "number": 2,
+    "pull_request": {
+      "number": 2,
+      "state": "open",
+      "head": {
+        "label": "octocat:feature",
+        "ref": "feature",
+        "sha": "ec26c3e57ca3a959ca5aad62de7213c562f8c821",
+        "repo": {
+          "full_name": "octocat/hello-world",
+          "clone_url": "https://github.com/octocat/hello-world.git",
+          "ssh_url": "git@github.com:octocat/hello-world.git"
+        }
+      },
+      "base": { "ref": "main", "sha": "f95f852bd8fca8fcc58a9a2d6c842781e32a215e" }
+    }
+  }"#;
+
+  const GITEA_PULL_REQUEST: &str = r#"{
+    "action": "synchronized",
+    "number": 7,
+    "pull_request": {
+      "id": 31,
+      "head": {
+        "label": "feature",
+        "ref": "feature",
+        "sha": "4fa4d3b6f6a1a8c1b4d42e4fb5d0c8c7a1a32ae5",
+        "repo": {
+          "full_name": "alice/hello-world",
+          "clone_url": "https://gitea.example.com/alice/hello-world.git",
+          "ssh_url": "git@gitea.example.com:alice/hello-world.git"
+        }
+      }
+    }
+  }"#;
+
+  const GITLAB_PUSH: &str = r#"{
+    "object_kind": "push",
+    "before": "95790bf891e76fee5e1747ab589903a6a1f80f22",
+    "after": "da1560886d4f094c3e6c9ef40349f7d38b5d27d7",
+    "ref": "refs/heads/master",
+    "checkout_sha": "da1560886d4f094c3e6c9ef40349f7d38b5d27d7",
+    "project": {
+      "id": 15,
+      "path_with_namespace": "mike/diaspora",
+      "git_http_url": "http://example.com/mike/diaspora.git",
+      "git_ssh_url": "git@example.com:mike/diaspora.git"
+    }
+  }"#;
+
+  const GITLAB_MERGE_REQUEST: &str = r#"{
+    "object_kind": "merge_request",
+    "event_type": "merge_request",
+    "object_attributes": {
+      "iid": 1,
+      "action": "update",
+      "oldrev": "fd4ec3d9e1d5e6b2d1a8e9d7a8a1b3c5d7e9f1a3",
+      "state": "opened",
+      "last_commit": { "id": "da1560886d4f094c3e6c9ef40349f7d38b5d27d7" },
+      "source": {
+        "path_with_namespace": "awesome_space/awesome_project",
+        "git_http_url": "http://example.com/awesome_space/awesome_project.git",
+        "git_ssh_url": "git@example.com:awesome_space/awesome_project.git"
+      }
+    }
+  }"#;
+
+  fn with(payload: &str, path: &[&str], value: serde_json::Value) -> Vec<u8> {
+    let mut json: serde_json::Value = serde_json::from_str(payload).unwrap();
+    let (last, path) = path.split_last().unwrap();
+    let parent = path.iter().fold(&mut json, |j, k| &mut j[k]);
+    parent[last] = value;
+    serde_json::to_vec(&json).unwrap()
+  }
+
+  #[test]
+  fn github_push() {
+    assert_eq!(
+      parse_github("push", GITHUB_PUSH.as_bytes()).unwrap(),
+      Some(HookBuild {
+        origins: vec![
+          "https://github.com/octocat/hello-world.git".into(),
+          "git@github.com:octocat/hello-world.git".into()
+        ],
+        rev: "0d1a26e67d8f5eaf1f6ba5c57fc3c7d91ac0fd1c".into(),
+        branch: Some("main".into()),
+      })
+    );
+  }
+
+  #[test]
+  fn deleted_branches_and_tags_arent_built() {
+    let deleted = with(GITHUB_PUSH, &["after"], json!("0".repeat(40)));
+    assert_eq!(parse_github("push", &deleted).unwrap(), None);
+
+    let tag = with(GITHUB_PUSH, &["ref"], json!("refs/tags/v1.0"));
+    assert_eq!(parse_github("push", &tag).unwrap(), None);
+
+    let deleted = with(GITLAB_PUSH, &["after"], json!("0".repeat(40)));
+    assert_eq!(parse_gitlab("Push Hook", &deleted).unwrap(), None);
+
+    let tag = with(GITLAB_PUSH, &["ref"], json!("refs/tags/v1.0"));
+    assert_eq!(parse_gitlab("Push Hook", &tag).unwrap(), None);
+  }
+
+  #[test]
+  fn github_pull_request() {
+    assert_eq!(
+      parse_github("pull_request", GITHUB_PULL_REQUEST.as_bytes()).unwrap(),
+      Some(HookBuild {
+        origins: vec![
+          "https://github.com/octocat/hello-world.git".into(),
+          "git@github.com:octocat/hello-world.git".into()
+        ],
+        rev: "ec26c3e57ca3a959ca5aad62de7213c562f8c821".into(),
+        branch: None,
+      })
+    );
+
+    let closed = with(GITHUB_PULL_REQUEST, &["action"], json!("closed"));
+    assert_eq!(parse_github("pull_request", &closed).unwrap(), None);
+  }
+
+  #[test]
+  fn pull_requests_from_deleted_forks_arent_built() {
+    let gone = with(
+      GITHUB_PULL_REQUEST,
+      &["pull_request", "head", "repo"],
+      json!(null),
+    );
+    assert_eq!(parse_github("pull_request", &gone).unwrap(), None);
+  }
+
+  #[test]
+  fn gitea_pull_request() {
+    let hook = parse_gitea("pull_request", GITEA_PULL_REQUEST.as_bytes())
+      .unwrap()
+      .unwrap();
+    assert_eq!(hook.rev, "4fa4d3b6f6a1a8c1b4d42e4fb5d0c8c7a1a32ae5");
+    assert_eq!(
+      hook.origins[0],
+      "https://gitea.example.com/alice/hello-world.git"
+    );
+
+    // github's name for it means nothing to gitea
+    let github = with(GITEA_PULL_REQUEST, &["action"], json!("synchronize"));
+    assert_eq!(parse_gitea("pull_request", &github).unwrap(), None);
+  }
+
+  #[test]
+  fn gitlab_push() {
+    assert_eq!(
+      parse_gitlab("Push Hook", GITLAB_PUSH.as_bytes()).unwrap(),
+      Some(HookBuild {
+        origins: vec![
+          "http://example.com/mike/diaspora.git".into(),
+          "git@example.com:mike/diaspora.git".into()
+        ],
+        rev: "da1560886d4f094c3e6c9ef40349f7d38b5d27d7".into(),
+        branch: Some("master".into()),
+      })
+    );
+  }
+
+  #[test]
+  fn gitlab_merge_request() {
+    assert_eq!(
+      parse_gitlab("Merge Request Hook", GITLAB_MERGE_REQUEST.as_bytes()).unwrap(),
+      Some(HookBuild {
+        origins: vec![
+          "http://example.com/awesome_space/awesome_project.git".into(),
+          "git@example.com:awesome_space/awesome_project.git".into()
+        ],
+        rev: "da1560886d4f094c3e6c9ef40349f7d38b5d27d7".into(),
+        branch: None,
+      })
+    );
+
+    // updates that only changed the title or labels don't have an oldrev
+    let retitled = with(
+      GITLAB_MERGE_REQUEST,
+      &["object_attributes", "oldrev"],
+      json!(null),
+    );
+    assert_eq!(parse_gitlab("Merge Request Hook", &retitled).unwrap(), None);
+
+    let merged = with(
+      GITLAB_MERGE_REQUEST,
+      &["object_attributes", "action"],
+      json!("merge"),
+    );
+    assert_eq!(parse_gitlab("Merge Request Hook", &merged).unwrap(), None);
+  }
+
+  #[test]
+  fn other_events_are_ignored() {
+    assert_eq!(parse_github("ping", b"{}").unwrap(), None);
+    assert_eq!(parse_gitlab("Note Hook", b"{}").unwrap(), None);
+    assert!(parse_github("push", b"{}").is_err());
+  }
+
+  #[test]
+  fn signatures() {
+    // the example from github's docs
+    let secret = "It's a Secret to Everybody";
+    let signature = "757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17";
+    assert!(is_signed(secret, b"Hello, World!", signature));
+    assert!(is_signed(
+      secret,
+      b"Hello, World!",
+      &signature.to_uppercase()
+    ));
+
+    assert!(!is_signed(secret, b"Hello, World?", signature));
+    assert!(!is_signed(
+      "It's a secret to everybody",
+      b"Hello, World!",
+      signature
+    ));
+    assert!(!is_signed(secret, b"Hello, World!", &signature[..62]));
+    assert!(!is_signed(secret, b"Hello, World!", "not hex"));
+    assert!(!is_signed(secret, b"Hello, World!", ""));
+  }
+}
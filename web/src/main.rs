@@ -1,28 +1,68 @@
 #![feature(try_blocks)]
 
+use std::path::PathBuf;
+
 use actix_files::{Files, NamedFile};
 use actix_web::http::header::Accept;
 use actix_web::{get, guard, put, web, App, HttpResponse, HttpServer, Responder};
 use anyhow::Context;
 use askama::Template;
+use audit::Identity;
 use cfg::Config;
-use common::{BoxDynError, Build};
+use chrono::{DateTime, Utc};
+use common::{
+  is_commit_hash, BoxDynError, Build, BuildStatus, InputDef, NewBuild, Project, WorkerInfo,
+};
+use error::ApiError;
 use serde::Deserialize;
 use serde_json::json;
 use sqlx::PgPool;
+use validate::Paths;
 
+mod audit;
+mod cache;
 mod cfg;
+mod error;
+mod hooks;
+mod projects;
 mod tail;
+mod validate;
+mod webhooks;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize)]
 struct BuildPlsNew {
-  origin: String,
-  rev: String,
-  paths: String,
+  // builds for a project can leave out origin and rev, and get its inputs,
+  // systems and publish setting unless they give their own
+  project: Option<String>,
+  origin: Option<String>,
+  // a commit, or a branch to build the tip of
+  rev: Option<String>,
+  // where rev came from, if it's a commit
+  branch: Option<String>,
+  // a list, or comma separated
+  #[serde(default)]
+  paths: Paths,
+  // same as paths. each one is a flake output like `.#hydraJobs` or a file like
+  // `release.nix`, and every derivation in it gets built
+  #[serde(default)]
+  jobsets: Paths,
+  // higher goes first
+  #[serde(default)]
+  priority: i32,
+  // overrides the worker's build_timeout_secs
+  timeout_secs: Option<i32>,
+  // overrides the worker's target_platforms
+  systems: Option<Vec<String>>,
+  // same as paths and jobsets, but each one can pick its own systems, arguments
+  // and so on. if none of them are given, the worker looks for a .starfish.toml
+  // in the repository instead
+  #[serde(default)]
+  inputs: Vec<InputDef>,
 }
 
-fn wrap<T, E: std::error::Error + 'static>(thing: Result<T, E>) -> actix_web::Result<T> {
-  thing.map_err(|e| actix_web::error::ErrorInternalServerError(e))
+#[derive(Debug, Deserialize)]
+struct Priority {
+  priority: i32,
 }
 
 fn content_type_guard<E: PartialEq<mime::Mime>>(ty: E) -> impl guard::Guard {
@@ -33,6 +73,11 @@ fn content_type_guard<E: PartialEq<mime::Mime>>(ty: E) -> impl guard::Guard {
   })
 }
 
+// anything under /api that none of the endpoints took
+async fn api_fallback() -> Result<HttpResponse, ApiError> {
+  Err(ApiError::NotFound("endpoint"))
+}
+
 async fn index() -> impl Responder {
   #[derive(Template)]
   #[template(path = "index.html")]
@@ -51,104 +96,522 @@ async fn index() -> impl Responder {
   )
 }
 
+#[derive(Debug, Deserialize)]
+struct BuildsQuery {
+  // comma separated
+  status: Option<String>,
+  origin: Option<String>,
+  // the start of a commit hash or branch name
+  rev: Option<String>,
+  since: Option<DateTime<Utc>>,
+  until: Option<DateTime<Utc>>,
+  submitted_by: Option<String>,
+  // builds that come after this one, for paging
+  before: Option<i32>,
+  limit: Option<i64>,
+}
+
+// newest first
 #[get("builds")]
-async fn get_builds(db: web::Data<PgPool>) -> actix_web::Result<impl Responder> {
-  Ok(web::Json(wrap(
+async fn get_builds(
+  db: web::Data<PgPool>,
+  query: web::Query<BuildsQuery>,
+) -> Result<impl Responder, ApiError> {
+  let statuses = match &query.status {
+    Some(status) => Some(
+      status
+        .split(',')
+        .map(|s| {
+          serde_json::from_value::<BuildStatus>(json!(s.trim()))
+            .map(|_| s.trim().to_string())
+            .map_err(|_| ApiError::BadRequest(format!("{s:?} isn't a status")))
+        })
+        .collect::<Result<Vec<_>, _>>()?,
+    ),
+    None => None,
+  };
+  // so `_` and `%` in it aren't wildcards
+  let rev_pattern = query.rev.as_ref().map(|rev| {
+    format!(
+      "{}%",
+      rev
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+    )
+  });
+
+  Ok(web::Json(
     sqlx::query_as!(
       Build,
-      "SELECT id, origin, created_at, error_msg, finished_at, rev, status as \"status: _\" FROM \
-       builds ORDER BY created_at DESC LIMIT 10"
+      "SELECT id, origin, created_at, error_msg, finished_at, rev, status as \"status: _\", \
+       worker_id, priority, timeout_secs, failure_kind as \"failure_kind: _\", systems, \
+       definition as \"definition: _\", project_id, branch, publish, submitted_by FROM builds \
+       WHERE ($1::text[] IS NULL OR status = any($1::text[]::build_status[])) AND ($2::text IS \
+       NULL OR origin = $2) AND ($3::text IS NULL OR rev LIKE $3) AND ($4::timestamptz IS NULL OR \
+       created_at >= $4) AND ($5::timestamptz IS NULL OR created_at < $5) AND ($6::text IS NULL \
+       OR submitted_by = $6) AND ($7::int IS NULL OR (created_at, id) < (SELECT created_at, id \
+       FROM builds WHERE id = $7)) ORDER BY created_at DESC, id DESC LIMIT $8",
+      statuses.as_deref(),
+      query.origin.as_deref(),
+      rev_pattern,
+      query.since,
+      query.until,
+      query.submitted_by.as_deref(),
+      query.before,
+      query.limit.unwrap_or(10).clamp(1, 100)
     )
     .fetch_all(&**db)
-    .await,
-  )?))
+    .await?,
+  ))
+}
+
+#[get("workers")]
+async fn get_workers(db: web::Data<PgPool>) -> Result<impl Responder, ApiError> {
+  let workers = WorkerInfo::all(&**db).await?;
+  let queued = sqlx::query_scalar!(
+    "SELECT count(*) as \"count!\" FROM builds WHERE status = $1",
+    BuildStatus::Queued as _
+  )
+  .fetch_one(&**db)
+  .await?;
+
+  Ok(web::Json(json!({ "workers": workers, "queued": queued })))
+}
+
+// turns inputs into the args and env stored with them, and makes sure some
+// worker is able to build everything that was asked for
+pub(crate) async fn check_inputs(
+  cfg: &Config,
+  db: &PgPool,
+  systems: Option<&Vec<String>>,
+  inputs: &[InputDef],
+) -> Result<Vec<(Vec<String>, Vec<String>)>, ApiError> {
+  let input_args = inputs
+    .iter()
+    .map(|i| i.nix_args(&cfg.allowed_options, &cfg.allowed_env))
+    .collect::<Result<Vec<_>, _>>()
+    .map_err(|e| ApiError::invalid("invalid_input", e))?;
+
+  let requested = systems
+    .into_iter()
+    .chain(inputs.iter().filter_map(|i| i.systems.as_ref()))
+    .collect::<Vec<_>>();
+  if requested.iter().any(|s| s.is_empty()) {
+    return Err(ApiError::invalid(
+      "invalid_systems",
+      "systems can't be empty",
+    ));
+  }
+  let supported =
+    sqlx::query_scalar!("SELECT DISTINCT unnest(systems) as \"system!\" FROM workers")
+      .fetch_all(db)
+      .await?;
+  if let Some(unsupported) = requested
+    .into_iter()
+    .flatten()
+    .find(|s| !supported.contains(s))
+  {
+    return Err(ApiError::invalid(
+      "unsupported_system",
+      format!("no worker can build for {unsupported}"),
+    ));
+  }
+
+  Ok(input_args)
 }
 
 #[put("build")]
 async fn put_build(
+  cfg: web::Data<Config>,
   db: web::Data<PgPool>,
   build: web::Json<BuildPlsNew>,
-) -> actix_web::Result<impl Responder> {
-  let new_build = wrap(
-    sqlx::query_as!(
-      Build,
-      "INSERT INTO builds (origin, rev) VALUES ($1, $2) RETURNING id, origin, rev, created_at, \
-       status as \"status: _\", finished_at, error_msg",
-      &build.origin,
-      &build.rev
-    )
-    .fetch_one(&**db)
-    .await,
-  )?;
+  identity: Identity,
+) -> Result<impl Responder, ApiError> {
+  Ok(web::Json(
+    queue_build(&cfg, &db, &build, identity.0.as_deref()).await?,
+  ))
+}
 
-  let mut all_paths = vec![];
+pub(crate) async fn queue_build(
+  cfg: &Config,
+  db: &PgPool,
+  build: &BuildPlsNew,
+  submitted_by: Option<&str>,
+) -> Result<Build, ApiError> {
+  if build.timeout_secs.map_or(false, |t| t <= 0) {
+    return Err(ApiError::invalid(
+      "invalid_timeout",
+      "timeout_secs must be positive",
+    ));
+  }
 
-  for path in build.paths.split(',') {
-    let path = path.trim();
-    if !path.is_empty() {
-      all_paths.push(path.to_string());
-    }
+  let project = match &build.project {
+    Some(name) => match Project::get(name, db).await? {
+      Some(project) => Some(project),
+      None => {
+        return Err(ApiError::invalid(
+          "unknown_project",
+          format!("there's no project called {name:?}"),
+        ))
+      }
+    },
+    None => None,
+  };
+
+  if let Some(origin) = &build.origin {
+    validate::origin(cfg, origin)?;
+  }
+  if let Some(rev) = &build.rev {
+    validate::rev("rev", rev)?;
+  }
+  if let Some(branch) = &build.branch {
+    validate::rev("branch", branch)?;
   }
+  let Some(origin) = build
+    .origin
+    .as_ref()
+    .or(project.as_ref().map(|p| &p.origin)) else {
+    return Err(ApiError::invalid("missing_origin", "origin is required"))
+  };
+  let Some(rev) = build
+    .rev
+    .as_ref()
+    .or(project.as_ref().map(|p| &p.default_branch)) else {
+    return Err(ApiError::invalid("missing_rev", "rev is required"))
+  };
+  let branch = build
+    .branch
+    .as_deref()
+    .or((!is_commit_hash(rev)).then_some(rev.as_str()));
 
-  // extremely budget multi insert because sqlx doesn't support it
-  wrap(
-    sqlx::query!(
-      "INSERT INTO inputs (build_id, path) SELECT $1, * FROM UNNEST($2::text[])",
-      new_build.id,
-      all_paths.as_slice()
-    )
-    .execute(&**db)
-    .await,
-  )?;
-
-  wrap(
-    sqlx::query!(
-      "SELECT pg_notify($1, $2)",
-      "build_queued",
-      new_build.id.to_string()
-    )
-    .execute(&**db)
-    .await,
-  )?;
+  let (inputs, systems, publish) = match &project {
+    Some(project) => (
+      if build.inputs.is_empty() && build.paths.is_empty() && build.jobsets.is_empty() {
+        &project.inputs[..]
+      } else {
+        &build.inputs[..]
+      },
+      build.systems.as_ref().or(project.systems.as_ref()),
+      project.publish.as_deref(),
+    ),
+    None => (&build.inputs[..], build.systems.as_ref(), None),
+  };
+
+  let input_args = check_inputs(cfg, db, systems, inputs).await?;
+
+  let mut path_inputs = vec![];
+  for (field, paths, jobset) in [
+    ("paths", &build.paths, false),
+    ("jobsets", &build.jobsets, true),
+  ] {
+    path_inputs.extend(paths.get(field)?.into_iter().map(|path| InputDef {
+      path: path.to_string(),
+      jobset,
+      ..Default::default()
+    }));
+  }
+
+  let new_build = NewBuild {
+    origin,
+    rev,
+    branch,
+    priority: build.priority,
+    timeout_secs: build.timeout_secs,
+    systems: systems.map(|s| s.as_slice()),
+    project_id: project.as_ref().map(|p| p.id),
+    publish,
+    submitted_by,
+    inputs: path_inputs
+      .iter()
+      .map(|input| (input, vec![], vec![]))
+      .chain(
+        inputs
+          .iter()
+          .zip(input_args)
+          .map(|(input, (args, env))| (input, args, env)),
+      )
+      .collect(),
+  };
 
-  Ok(web::Json(new_build))
+  let mut tx = db.begin().await?;
+  let new_build = new_build.enqueue(&mut tx).await?;
+  tx.commit().await?;
+
+  Ok(new_build)
 }
 
 #[get("build/{id}")]
-async fn get_build(db: web::Data<PgPool>, id: web::Path<i32>) -> actix_web::Result<impl Responder> {
-  let Some(build) = wrap(Build::get(*id, &**db).await)? else {
-    return Ok(None)
+async fn get_build(db: web::Data<PgPool>, id: web::Path<i32>) -> Result<impl Responder, ApiError> {
+  let Some(build) = Build::get(*id, &**db).await? else {
+    return Err(ApiError::NotFound("build"))
+  };
+
+  let inputs = build.get_inputs_and_outputs(&**db).await?;
+
+  Ok(web::Json(json!({ "build": build, "inputs": inputs })))
+}
+
+#[get("build/{id}/attempts")]
+async fn get_build_attempts(
+  db: web::Data<PgPool>,
+  id: web::Path<i32>,
+) -> Result<impl Responder, ApiError> {
+  let Some(build) = Build::get(*id, &**db).await? else {
+    return Err(ApiError::NotFound("build"))
   };
 
-  let inputs = wrap(build.get_inputs_and_outputs(&**db).await)?;
+  Ok(web::Json(build.get_attempts(&**db).await?))
+}
+
+// finds the log for the given attempt, or the latest one. builds from before
+// attempts were tracked only have the one log
+pub(crate) async fn logfile(
+  cfg: &Config,
+  db: &PgPool,
+  id: i32,
+  attempt: Option<i32>,
+) -> Result<PathBuf, ApiError> {
+  let log_file = sqlx::query_scalar!(
+    "SELECT log_file FROM build_attempts WHERE build_id = $1 AND ($2::int IS NULL OR attempt = \
+     $2) ORDER BY attempt DESC LIMIT 1",
+    id,
+    attempt
+  )
+  .fetch_optional(db)
+  .await?;
 
-  Ok(Some(web::Json(json!({ "build": build, "inputs": inputs }))))
+  match (log_file, attempt) {
+    (Some(f), _) => Ok(cfg.log_path.join(f)),
+    (None, None) => Ok(cfg.log_path.join(format!("{id}.log"))),
+    (None, Some(_)) => Err(ApiError::NotFound("attempt")),
+  }
+}
+
+// builds that haven't started yet don't have one
+pub(crate) fn missing_log(e: std::io::Error) -> ApiError {
+  match e.kind() {
+    std::io::ErrorKind::NotFound => ApiError::NotFound("log"),
+    _ => e.into(),
+  }
+}
+
+async fn raw_log(
+  cfg: &Config,
+  db: &PgPool,
+  id: i32,
+  attempt: Option<i32>,
+) -> Result<NamedFile, ApiError> {
+  Ok(
+    NamedFile::open_async(logfile(cfg, db, id, attempt).await?)
+      .await
+      .map_err(missing_log)?
+      .set_content_type(mime::TEXT_PLAIN),
+  )
 }
 
 #[get("build/{id}/raw")]
-async fn get_build_raw(cfg: web::Data<Config>, id: web::Path<i32>) -> Option<NamedFile> {
-  NamedFile::open_async(cfg.logfile(*id))
-    .await
-    .ok()
-    .map(|x| x.set_content_type(mime::TEXT_PLAIN))
+async fn get_build_raw(
+  cfg: web::Data<Config>,
+  db: web::Data<PgPool>,
+  id: web::Path<i32>,
+) -> Result<NamedFile, ApiError> {
+  raw_log(&cfg, &db, *id, None).await
+}
+
+#[get("build/{id}/attempts/{attempt}/raw")]
+async fn get_build_attempt_raw(
+  cfg: web::Data<Config>,
+  db: web::Data<PgPool>,
+  path: web::Path<(i32, i32)>,
+) -> Result<NamedFile, ApiError> {
+  let (id, attempt) = path.into_inner();
+  raw_log(&cfg, &db, id, Some(attempt)).await
+}
+
+#[derive(Debug, Deserialize)]
+struct RestartOpts {
+  #[serde(default)]
+  force: bool,
 }
 
 #[put("build/{id}/restart")]
 async fn put_build_restart(
   db: web::Data<PgPool>,
   id: web::Path<i32>,
-) -> actix_web::Result<impl Responder> {
-  wrap(
-    sqlx::query!(
-      "SELECT pg_notify($1, $2)",
-      "build_restarted",
-      id.to_string()
+  opts: web::Query<RestartOpts>,
+  identity: Identity,
+) -> Result<impl Responder, ApiError> {
+  let mut tx = db.begin().await?;
+
+  let Some(build) = sqlx::query_as!(
+      Build,
+      "SELECT id, origin, rev, created_at, status as \"status: _\", finished_at, error_msg, \
+       worker_id, priority, timeout_secs, failure_kind as \"failure_kind: _\", systems, \
+       definition as \"definition: _\", project_id, branch, publish, submitted_by FROM builds \
+       WHERE id = $1 FOR UPDATE",
+      *id
+    )
+    .fetch_optional(&mut *tx)
+    .await? else {
+    return Err(ApiError::NotFound("build"))
+  };
+
+  if matches!(build.status, BuildStatus::Building | BuildStatus::Uploading) && !opts.force {
+    return Err(ApiError::Conflict(
+      "build is still running, use force=true to restart it anyway".into(),
+    ));
+  }
+
+  // the build goes back in the queue right away, so it gets picked up even if
+  // no worker happens to be listening for the notification
+  let build = sqlx::query_as!(
+    Build,
+    "UPDATE builds SET status = $2, finished_at = NULL, error_msg = NULL, failure_kind = NULL, \
+     retries = 0 WHERE id = $1 RETURNING id, origin, rev, created_at, status as \"status: _\", \
+     finished_at, error_msg, worker_id, priority, timeout_secs, failure_kind as \"failure_kind: \
+     _\", systems, definition as \"definition: _\", project_id, branch, publish, submitted_by",
+    *id,
+    BuildStatus::Queued as _
+  )
+  .fetch_one(&mut *tx)
+  .await?;
+
+  sqlx::query!(
+    "SELECT pg_notify($1, $2)",
+    "build_restarted",
+    id.to_string()
+  )
+  .execute(&mut *tx)
+  .await?;
+
+  audit::record(
+    &mut *tx,
+    &identity,
+    "restart",
+    Some(build.id),
+    opts.force.then(|| json!({ "force": true })),
+  )
+  .await?;
+
+  tx.commit().await?;
+
+  Ok(web::Json(build))
+}
+
+#[put("build/{id}/cancel")]
+async fn put_build_cancel(
+  db: web::Data<PgPool>,
+  id: web::Path<i32>,
+  identity: Identity,
+) -> Result<impl Responder, ApiError> {
+  let mut tx = db.begin().await?;
+
+  let Some(status) = sqlx::query_scalar!(
+      "SELECT status as \"status: BuildStatus\" FROM builds WHERE id = $1 FOR UPDATE",
+      *id
+    )
+    .fetch_optional(&mut *tx)
+    .await? else {
+    return Err(ApiError::NotFound("build"))
+  };
+
+  match status {
+    // nobody is working on it, so nobody would hear a notification
+    BuildStatus::Queued => {
+      let now = Utc::now();
+      sqlx::query!(
+        "UPDATE builds SET status = $2, finished_at = $3 WHERE id = $1",
+        *id,
+        BuildStatus::Canceled as _,
+        now
+      )
+      .execute(&mut *tx)
+      .await?;
+      // a forced restart can leave the previous attempt open
+      sqlx::query!(
+        "UPDATE build_attempts SET status = $2, finished_at = $3 WHERE build_id = $1 AND \
+         finished_at IS NULL",
+        *id,
+        BuildStatus::Canceled as _,
+        now
+      )
+      .execute(&mut *tx)
+      .await?;
+    }
+    // the worker running it cancels it and cleans up after itself
+    BuildStatus::Building | BuildStatus::Uploading => {
+      sqlx::query!("SELECT pg_notify($1, $2)", "build_canceled", id.to_string())
+        .execute(&mut *tx)
+        .await?;
+    }
+    BuildStatus::Succeeded | BuildStatus::Failed | BuildStatus::Canceled => {
+      return Err(ApiError::Conflict(
+        "build already finished, it can't be canceled".into(),
+      ));
+    }
+  }
+
+  audit::record(&mut *tx, &identity, "cancel", Some(*id), None).await?;
+
+  // a running build is still running until its worker gets to it
+  let build = Build::get(*id, &mut *tx)
+    .await?
+    .ok_or(ApiError::NotFound("build"))?;
+
+  tx.commit().await?;
+
+  Ok(web::Json(build))
+}
+
+// lets a build that's still waiting jump the queue (or fall behind)
+#[put("build/{id}/priority")]
+async fn put_build_priority(
+  db: web::Data<PgPool>,
+  id: web::Path<i32>,
+  priority: web::Json<Priority>,
+  identity: Identity,
+) -> Result<impl Responder, ApiError> {
+  let mut tx = db.begin().await?;
+
+  let Some(status) = sqlx::query_scalar!(
+      "SELECT status as \"status: BuildStatus\" FROM builds WHERE id = $1 FOR UPDATE",
+      *id
     )
-    .execute(&**db)
-    .await,
-  )?;
+    .fetch_optional(&mut *tx)
+    .await? else {
+    return Err(ApiError::NotFound("build"))
+  };
+  if !matches!(status, BuildStatus::Queued) {
+    return Err(ApiError::Conflict(
+      "only queued builds can be reprioritized".into(),
+    ));
+  }
+
+  let build = sqlx::query_as!(
+    Build,
+    "UPDATE builds SET priority = $2 WHERE id = $1 RETURNING id, origin, rev, created_at, status \
+     as \"status: _\", finished_at, error_msg, worker_id, priority, timeout_secs, failure_kind as \
+     \"failure_kind: _\", systems, definition as \"definition: _\", project_id, branch, publish, \
+     submitted_by",
+    *id,
+    priority.priority
+  )
+  .fetch_one(&mut *tx)
+  .await?;
+
+  audit::record(
+    &mut *tx,
+    &identity,
+    "priority",
+    Some(build.id),
+    Some(json!({ "priority": build.priority })),
+  )
+  .await?;
+
+  tx.commit().await?;
 
-  Ok(web::Json(json!({"success": true})))
+  Ok(web::Json(build))
 }
 
 #[actix_web::main]
@@ -168,15 +631,42 @@ async fn main() -> Result<(), BoxDynError> {
       App::new()
         .service(Files::new("/static", &cfg.static_root))
         .service(
+          // everything in here answers with json (or an event stream for the
+          // tails), whatever the request says it accepts
           web::scope("/api")
-            .guard(content_type_guard(mime::APPLICATION_JSON))
+            .app_data(web::PayloadConfig::new(hooks::MAX_PAYLOAD))
             .service(get_builds)
+            .service(get_workers)
             .service(get_build)
+            .service(get_build_attempts)
             .service(put_build)
-            .service(put_build_restart),
+            .service(put_build_restart)
+            .service(put_build_cancel)
+            .service(put_build_priority)
+            .service(projects::get_projects)
+            .service(projects::put_project)
+            .service(projects::get_project)
+            .service(projects::put_project_update)
+            .service(projects::delete_project)
+            .service(projects::get_project_builds)
+            .service(webhooks::get_webhooks)
+            .service(webhooks::put_webhook)
+            .service(webhooks::delete_webhook)
+            .service(webhooks::get_webhook_deliveries)
+            .service(webhooks::put_redeliver)
+            .service(audit::get_audit)
+            .service(tail::get_build_tail)
+            .service(tail::get_build_attempt_tail)
+            .service(hooks::post_github)
+            .service(hooks::post_gitea)
+            .service(hooks::post_gitlab)
+            .default_service(web::to(api_fallback)),
         )
-        .service(web::scope("/api").service(tail::get_build_tail))
         .service(get_build_raw)
+        .service(get_build_attempt_raw)
+        .service(cache::get_cache_info)
+        .service(cache::get_narinfo)
+        .service(cache::get_nar)
         .route(
           "/{_:.*}",
           web::get()
@@ -185,6 +675,9 @@ async fn main() -> Result<(), BoxDynError> {
         )
         .app_data(web::Data::new(pg.clone()))
         .app_data(web::Data::new(cfg.clone()))
+        .app_data(web::JsonConfig::default().error_handler(error::bad_request))
+        .app_data(web::QueryConfig::default().error_handler(error::bad_request))
+        .app_data(web::PathConfig::default().error_handler(error::bad_request))
         .wrap(actix_web::middleware::Logger::default())
     })
     .bind(listen_addr)?
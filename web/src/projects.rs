@@ -0,0 +1,186 @@
+use actix_web::{delete, get, put, web, Responder};
+use common::{Build, InputDef, Project};
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::types::Json;
+use sqlx::PgPool;
+
+use crate::error::ApiError;
+use crate::{check_inputs, validate, Config};
+
+#[derive(Debug, Deserialize)]
+pub struct ProjectPlsNew {
+  name: String,
+  origin: String,
+  #[serde(default = "default_branch")]
+  default_branch: String,
+  // same as a build's inputs. if there are none, builds use the repository's
+  // .starfish.toml
+  #[serde(default)]
+  inputs: Vec<InputDef>,
+  systems: Option<Vec<String>>,
+  // one of the worker's publish_targets, or "none"
+  publish: Option<String>,
+  // branches to build whenever they change, if a worker is polling
+  #[serde(default)]
+  poll_branches: Vec<String>,
+}
+
+fn default_branch() -> String {
+  "main".into()
+}
+
+impl ProjectPlsNew {
+  async fn check(&self, cfg: &Config, db: &PgPool) -> Result<(), ApiError> {
+    // it ends up in urls
+    if self.name.is_empty() || self.name.contains('/') {
+      return Err(ApiError::invalid(
+        "invalid_name",
+        "project names can't be empty or contain /",
+      ));
+    }
+    validate::origin(cfg, &self.origin)?;
+    validate::rev("default_branch", &self.default_branch)?;
+    for branch in &self.poll_branches {
+      validate::rev("poll_branches", branch)?;
+    }
+    check_inputs(cfg, db, self.systems.as_ref(), &self.inputs).await?;
+    Ok(())
+  }
+}
+
+fn conflict_on_duplicate<T>(thing: sqlx::Result<T>) -> Result<T, ApiError> {
+  match thing {
+    Err(sqlx::Error::Database(e)) if e.is_unique_violation() => Err(ApiError::Conflict(
+      "there's already a project with that name".into(),
+    )),
+    thing => Ok(thing?),
+  }
+}
+
+#[get("projects")]
+pub(crate) async fn get_projects(db: web::Data<PgPool>) -> Result<impl Responder, ApiError> {
+  Ok(web::Json(
+    sqlx::query_as!(
+      Project,
+      "SELECT id, name, origin, default_branch, inputs as \"inputs: _\", systems, publish, \
+       poll_branches, created_at FROM projects ORDER BY name"
+    )
+    .fetch_all(&**db)
+    .await?,
+  ))
+}
+
+#[put("projects")]
+pub(crate) async fn put_project(
+  cfg: web::Data<Config>,
+  db: web::Data<PgPool>,
+  project: web::Json<ProjectPlsNew>,
+) -> Result<impl Responder, ApiError> {
+  project.check(&cfg, &db).await?;
+
+  Ok(web::Json(conflict_on_duplicate(
+    sqlx::query_as!(
+      Project,
+      "INSERT INTO projects (name, origin, default_branch, inputs, systems, publish, \
+       poll_branches) VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING id, name, origin, \
+       default_branch, inputs as \"inputs: _\", systems, publish, poll_branches, created_at",
+      &project.name,
+      &project.origin,
+      &project.default_branch,
+      Json(&project.inputs) as _,
+      project.systems.as_deref(),
+      project.publish.as_deref(),
+      &project.poll_branches
+    )
+    .fetch_one(&**db)
+    .await,
+  )?))
+}
+
+#[get("projects/{name}")]
+pub(crate) async fn get_project(
+  db: web::Data<PgPool>,
+  name: web::Path<String>,
+) -> Result<impl Responder, ApiError> {
+  match Project::get(&name, &**db).await? {
+    Some(project) => Ok(web::Json(project)),
+    None => Err(ApiError::NotFound("project")),
+  }
+}
+
+// replaces everything about the project, including its name
+#[put("projects/{name}")]
+pub(crate) async fn put_project_update(
+  cfg: web::Data<Config>,
+  db: web::Data<PgPool>,
+  name: web::Path<String>,
+  project: web::Json<ProjectPlsNew>,
+) -> Result<impl Responder, ApiError> {
+  project.check(&cfg, &db).await?;
+
+  let updated = conflict_on_duplicate(
+    sqlx::query_as!(
+      Project,
+      "UPDATE projects SET name = $2, origin = $3, default_branch = $4, inputs = $5, systems = \
+       $6, publish = $7, poll_branches = $8 WHERE name = $1 RETURNING id, name, origin, \
+       default_branch, inputs as \"inputs: _\", systems, publish, poll_branches, created_at",
+      &*name,
+      &project.name,
+      &project.origin,
+      &project.default_branch,
+      Json(&project.inputs) as _,
+      project.systems.as_deref(),
+      project.publish.as_deref(),
+      &project.poll_branches
+    )
+    .fetch_optional(&**db)
+    .await,
+  )?;
+
+  match updated {
+    Some(project) => Ok(web::Json(project)),
+    None => Err(ApiError::NotFound("project")),
+  }
+}
+
+// the project's builds are kept, they just stop belonging to it
+#[delete("projects/{name}")]
+pub(crate) async fn delete_project(
+  db: web::Data<PgPool>,
+  name: web::Path<String>,
+) -> Result<impl Responder, ApiError> {
+  let deleted = sqlx::query!("DELETE FROM projects WHERE name = $1", &*name)
+    .execute(&**db)
+    .await?;
+  if deleted.rows_affected() == 0 {
+    return Err(ApiError::NotFound("project"));
+  }
+
+  Ok(web::Json(json!({"success": true})))
+}
+
+// the latest build of every branch. builds for a specific commit are grouped
+// together under a null branch
+#[get("projects/{name}/builds")]
+pub(crate) async fn get_project_builds(
+  db: web::Data<PgPool>,
+  name: web::Path<String>,
+) -> Result<impl Responder, ApiError> {
+  let Some(project) = Project::get(&name, &**db).await? else {
+    return Err(ApiError::NotFound("project"))
+  };
+
+  let builds = sqlx::query_as!(
+    Build,
+    "SELECT DISTINCT ON (branch) id, origin, rev, created_at, status as \"status: _\", \
+     finished_at, error_msg, worker_id, priority, timeout_secs, failure_kind as \"failure_kind: \
+     _\", systems, definition as \"definition: _\", project_id, branch, publish, submitted_by \
+     FROM builds WHERE project_id = $1 ORDER BY branch, created_at DESC, id DESC",
+    project.id
+  )
+  .fetch_all(&**db)
+  .await?;
+
+  Ok(web::Json(json!({ "project": project, "branches": builds })))
+}
@@ -10,8 +10,10 @@ use futures_util::StreamExt;
 use inotify::{EventMask, Inotify, WatchMask};
 use log::info;
 use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
 
-use crate::Config;
+use crate::error::ApiError;
+use crate::{missing_log, Config};
 
 #[derive(Deserialize)]
 pub struct LenSpec {
@@ -21,13 +23,35 @@ pub struct LenSpec {
 #[get("/build/{id}/tail")]
 pub(crate) async fn get_build_tail(
   wc: web::Data<Config>,
+  db: web::Data<PgPool>,
   id: web::Path<i32>,
   len: web::Query<LenSpec>,
-) -> actix_web::Result<impl Responder> {
+) -> Result<impl Responder, ApiError> {
+  tail(&wc, &db, *id, None, &len).await
+}
+
+#[get("/build/{id}/attempts/{attempt}/tail")]
+pub(crate) async fn get_build_attempt_tail(
+  wc: web::Data<Config>,
+  db: web::Data<PgPool>,
+  path: web::Path<(i32, i32)>,
+  len: web::Query<LenSpec>,
+) -> Result<impl Responder, ApiError> {
+  let (id, attempt) = path.into_inner();
+  tail(&wc, &db, id, Some(attempt), &len).await
+}
+
+async fn tail(
+  wc: &Config,
+  db: &PgPool,
+  id: i32,
+  attempt: Option<i32>,
+  len: &LenSpec,
+) -> Result<impl Responder, ApiError> {
   let tail_len = len.len.unwrap_or(20);
-  let log_path = wc.logfile(*id);
+  let log_path = crate::logfile(wc, db, id, attempt).await?;
 
-  let logfile = File::open(&log_path)?;
+  let logfile = File::open(&log_path).map_err(missing_log)?;
 
   let (sender, sse_stream) = sse::channel(10);
 
@@ -0,0 +1,231 @@
+use serde::Deserialize;
+
+use crate::error::ApiError;
+use crate::Config;
+
+/// `paths` and `jobsets` of a build request, either a list or the older comma
+/// separated string.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Paths {
+  List(Vec<String>),
+  Legacy(String),
+}
+
+impl Default for Paths {
+  fn default() -> Self {
+    Self::Legacy(String::new())
+  }
+}
+
+impl Paths {
+  /// Every path, trimmed. An empty string means there aren't any, but empty
+  /// entries and empty lists are mistakes.
+  pub fn get(&self, field: &str) -> Result<Vec<&str>, ApiError> {
+    let paths = match self {
+      Self::Legacy(s) if s.trim().is_empty() => return Ok(vec![]),
+      Self::Legacy(s) => s.split(',').map(str::trim).collect::<Vec<_>>(),
+      Self::List(l) if l.is_empty() => {
+        return Err(ApiError::invalid(
+          "empty_paths",
+          format!("{field} can't be an empty list, leave it out instead"),
+        ))
+      }
+      Self::List(l) => l.iter().map(|p| p.trim()).collect(),
+    };
+    for path in &paths {
+      common::check_path(path)
+        .map_err(|e| ApiError::invalid("invalid_path", format!("{field}: {e}")))?;
+    }
+    Ok(paths)
+  }
+
+  pub fn is_empty(&self) -> bool {
+    match self {
+      Self::Legacy(s) => s.trim().is_empty(),
+      Self::List(l) => l.is_empty(),
+    }
+  }
+}
+
+/// Repository urls have to use one of the configured schemes. `user@host:path`
+/// counts as ssh, and plain paths as file.
+pub fn origin(cfg: &Config, origin: &str) -> Result<(), ApiError> {
+  let invalid = |why: &str| ApiError::invalid("invalid_origin", format!("{origin:?} {why}"));
+
+  // git would take it as an option
+  if origin.is_empty() || origin.starts_with('-') {
+    return Err(invalid("isn't a repository url"));
+  }
+  if origin.chars().any(|c| c.is_whitespace() || c.is_control()) {
+    return Err(invalid("can't contain whitespace"));
+  }
+
+  let scheme = match origin.split_once("://") {
+    Some((scheme, rest)) => {
+      if rest.trim_start_matches('/').is_empty() {
+        return Err(invalid("doesn't say where the repository is"));
+      }
+      scheme.to_lowercase()
+    }
+    None => match origin.split_once(':') {
+      Some((host, path)) if !host.contains('/') && !path.is_empty() => "ssh".into(),
+      _ => "file".into(),
+    },
+  };
+  if !cfg.origin_schemes.contains(&scheme) {
+    return Err(invalid(&format!(
+      "uses {scheme}, which isn't one of {}",
+      cfg.origin_schemes.join(", ")
+    )));
+  }
+  Ok(())
+}
+
+/// A commit hash, or a branch or tag name the way `git check-ref-format` would
+/// take it.
+pub fn rev(field: &str, rev: &str) -> Result<(), ApiError> {
+  let invalid = |why: &str| ApiError::invalid("invalid_rev", format!("{field} {rev:?} {why}"));
+
+  if rev.is_empty() {
+    return Err(invalid("can't be empty"));
+  }
+  if rev.len() > 255 {
+    return Err(invalid("is too long"));
+  }
+  if rev
+    .chars()
+    .any(|c| c.is_whitespace() || c.is_control() || "~^:?*[\\".contains(c))
+  {
+    return Err(invalid("can't contain whitespace or any of ~^:?*[\\"));
+  }
+  if rev.contains("..") || rev.contains("@{") || rev.contains("//") || rev == "@" {
+    return Err(invalid("isn't a valid git ref"));
+  }
+  if rev.starts_with(['-', '/']) || rev.ends_with(['/', '.']) || rev.ends_with(".lock") {
+    return Err(invalid("isn't a valid git ref"));
+  }
+  if rev.split('/').any(|part| part.starts_with('.')) {
+    return Err(invalid("isn't a valid git ref"));
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn cfg(schemes: Option<&[&str]>) -> Config {
+    let mut cfg = serde_json::json!({
+      "log_path": "/",
+      "static_root": "/",
+      "database_url": "",
+      "listen_address": "",
+      "listen_port": 1,
+    });
+    if let Some(schemes) = schemes {
+      cfg["origin_schemes"] = schemes.into();
+    }
+    serde_json::from_value(cfg).unwrap()
+  }
+
+  #[test]
+  fn origins() {
+    let cfg = cfg(None);
+    for ok in [
+      "https://github.com/someone/something.git",
+      "HTTPS://github.com/someone/something",
+      "ssh://git@example.com:2222/repo.git",
+      "git@github.com:someone/something.git",
+      "example.com:repo",
+      "git://example.com/repo",
+    ] {
+      assert!(origin(&cfg, ok).is_ok(), "{ok}");
+    }
+    for bad in [
+      "",
+      "-uhttps://example.com/repo",
+      "--upload-pack=touch /tmp/pwned",
+      "https://example.com/my repo",
+      "https://example.com/repo\n",
+      "https://",
+      "https:///",
+      "file:///etc",
+      "/srv/git/repo",
+      "./repo",
+      "ext::sh -c touch% /tmp/pwned",
+    ] {
+      assert!(origin(&cfg, bad).is_err(), "{bad}");
+    }
+  }
+
+  #[test]
+  fn origin_schemes_come_from_the_config() {
+    let cfg = cfg(Some(&["https", "file"]));
+    assert!(origin(&cfg, "https://example.com/repo").is_ok());
+    assert!(origin(&cfg, "/srv/git/repo").is_ok());
+    assert!(origin(&cfg, "git@github.com:someone/something.git").is_err());
+    assert!(origin(&cfg, "ssh://example.com/repo").is_err());
+  }
+
+  #[test]
+  fn revs() {
+    for ok in [
+      "main",
+      "feature/thing",
+      "v1.0",
+      "0d1a26e67d8f5eaf1f6ba5c57fc3c7d91ac0fd1c",
+      "a.lock.b",
+      "release-1.x",
+    ] {
+      assert!(rev("rev", ok).is_ok(), "{ok}");
+    }
+    for bad in [
+      "",
+      "-n",
+      "--orphan",
+      "main..other",
+      "../../etc",
+      "main@{upstream}",
+      "@",
+      "branch.lock",
+      "feature/.hidden",
+      ".hidden",
+      "feature//thing",
+      "/main",
+      "main/",
+      "main.",
+      "has space",
+      "a~1",
+      "a^",
+      "a:b",
+      "a?",
+      "a*",
+      "a[b",
+      "a\\b",
+      "a\nb",
+    ] {
+      assert!(rev("rev", bad).is_err(), "{bad:?}");
+    }
+    assert!(rev("rev", &"a".repeat(256)).is_err());
+  }
+
+  #[test]
+  fn paths() {
+    assert!(Paths::default().get("paths").unwrap().is_empty());
+    assert!(Paths::Legacy("  ".into()).get("paths").unwrap().is_empty());
+    assert_eq!(
+      Paths::Legacy("a.nix, b.nix".into()).get("paths").unwrap(),
+      ["a.nix", "b.nix"]
+    );
+    assert_eq!(
+      Paths::List(vec![" .#hello ".into()]).get("paths").unwrap(),
+      [".#hello"]
+    );
+
+    assert!(Paths::List(vec![]).get("paths").is_err());
+    assert!(Paths::Legacy("a.nix,".into()).get("paths").is_err());
+    assert!(Paths::List(vec!["../a.nix".into()]).get("paths").is_err());
+    assert!(Paths::List(vec!["--expr".into()]).get("paths").is_err());
+  }
+}
@@ -0,0 +1,161 @@
+use actix_web::{delete, get, put, web, Responder};
+use common::{Webhook, WebhookDelivery};
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::PgPool;
+use url::Url;
+
+use crate::error::ApiError;
+
+const EVENTS: [&str; 5] = ["queued", "started", "succeeded", "failed", "canceled"];
+
+#[derive(Debug, Deserialize)]
+pub struct WebhookPlsNew {
+  url: String,
+  secret: Option<String>,
+  events: Vec<String>,
+}
+
+#[get("webhooks")]
+pub(crate) async fn get_webhooks(db: web::Data<PgPool>) -> Result<impl Responder, ApiError> {
+  Ok(web::Json(
+    sqlx::query_as!(
+      Webhook,
+      "SELECT id, url, secret IS NOT NULL as \"signed!\", events, created_at FROM webhooks ORDER \
+       BY id"
+    )
+    .fetch_all(&**db)
+    .await?,
+  ))
+}
+
+#[put("webhooks")]
+pub(crate) async fn put_webhook(
+  db: web::Data<PgPool>,
+  webhook: web::Json<WebhookPlsNew>,
+) -> Result<impl Responder, ApiError> {
+  // the url ends up in curl's config on the worker, where a newline would start
+  // a new option. the parser would quietly drop it, so it's checked first
+  if webhook.url.chars().any(|c| c.is_control()) {
+    return Err(ApiError::invalid(
+      "invalid_url",
+      "url can't contain control characters",
+    ));
+  }
+  match Url::parse(&webhook.url) {
+    Ok(url) if matches!(url.scheme(), "http" | "https") && url.has_host() => {}
+    _ => {
+      return Err(ApiError::invalid(
+        "invalid_url",
+        "url has to be an http or https url",
+      ))
+    }
+  }
+  if webhook.events.is_empty() {
+    return Err(ApiError::invalid("invalid_events", "events can't be empty"));
+  }
+  if let Some(event) = webhook
+    .events
+    .iter()
+    .find(|e| !EVENTS.contains(&e.as_str()))
+  {
+    return Err(ApiError::invalid(
+      "invalid_events",
+      format!(
+        "{event:?} isn't an event, expected one of {}",
+        EVENTS.join(", ")
+      ),
+    ));
+  }
+
+  Ok(web::Json(
+    sqlx::query_as!(
+      Webhook,
+      "INSERT INTO webhooks (url, secret, events) VALUES ($1, $2, $3) RETURNING id, url, secret \
+       IS NOT NULL as \"signed!\", events, created_at",
+      &webhook.url,
+      webhook.secret.as_deref(),
+      &webhook.events
+    )
+    .fetch_one(&**db)
+    .await?,
+  ))
+}
+
+// deliveries that haven't gone out yet go with it
+#[delete("webhooks/{id}")]
+pub(crate) async fn delete_webhook(
+  db: web::Data<PgPool>,
+  id: web::Path<i32>,
+) -> Result<impl Responder, ApiError> {
+  let deleted = sqlx::query!("DELETE FROM webhooks WHERE id = $1", *id)
+    .execute(&**db)
+    .await?;
+  if deleted.rows_affected() == 0 {
+    return Err(ApiError::NotFound("webhook"));
+  }
+
+  Ok(web::Json(json!({"success": true})))
+}
+
+// the latest 50
+#[get("webhooks/{id}/deliveries")]
+pub(crate) async fn get_webhook_deliveries(
+  db: web::Data<PgPool>,
+  id: web::Path<i32>,
+) -> Result<impl Responder, ApiError> {
+  let exists = sqlx::query_scalar!(
+    "SELECT EXISTS (SELECT 1 FROM webhooks WHERE id = $1) as \"exists!\"",
+    *id
+  )
+  .fetch_one(&**db)
+  .await?;
+  if !exists {
+    return Err(ApiError::NotFound("webhook"));
+  }
+
+  Ok(web::Json(
+    sqlx::query_as!(
+      WebhookDelivery,
+      "SELECT d.id, d.webhook_id, d.build_id, d.event, d.created_at, d.payload as \"payload: _\", \
+       d.attempts, d.next_attempt_at, d.delivered_at, a.response_code as \"response_code?\", \
+       a.error as \"error?\" FROM webhook_deliveries d LEFT JOIN LATERAL (SELECT response_code, \
+       error FROM webhook_delivery_attempts WHERE delivery_id = d.id ORDER BY id DESC LIMIT 1) a \
+       ON true WHERE d.webhook_id = $1 ORDER BY d.id DESC LIMIT 50",
+      *id
+    )
+    .fetch_all(&**db)
+    .await?,
+  ))
+}
+
+// sends the same payload again, even if it went through the first time
+#[put("webhooks/deliveries/{id}/redeliver")]
+pub(crate) async fn put_redeliver(
+  db: web::Data<PgPool>,
+  id: web::Path<i32>,
+) -> Result<impl Responder, ApiError> {
+  let mut tx = db.begin().await?;
+
+  let Some(build_id) = sqlx::query_scalar!(
+      "UPDATE webhook_deliveries SET attempts = 0, next_attempt_at = now(), delivered_at = NULL \
+       WHERE id = $1 RETURNING build_id",
+      *id
+    )
+    .fetch_optional(&mut *tx)
+    .await? else {
+    return Err(ApiError::NotFound("delivery"))
+  };
+
+  sqlx::query!(
+    "SELECT pg_notify($1, $2)",
+    "webhook_delivery_queued",
+    build_id.to_string()
+  )
+  .execute(&mut *tx)
+  .await?;
+
+  tx.commit().await?;
+
+  Ok(web::Json(json!({"success": true})))
+}
@@ -1,4 +1,5 @@
 use std::borrow::Cow;
+use std::collections::HashMap;
 use std::path::PathBuf;
 
 use serde::Deserialize;
@@ -16,6 +17,42 @@ pub enum Publish {
     secret_key: String,
     nix_signing_key: String,
   },
+  // a file:// binary cache, for the web server to serve if it's pointed at the
+  // same directory
+  #[serde(alias = "local")]
+  Local {
+    path: PathBuf,
+    nix_signing_key: String,
+  },
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ForgeKind {
+  Github,
+  Gitea,
+  Gitlab,
+}
+
+// somewhere to report commit statuses to
+#[derive(Debug, Deserialize, Clone)]
+pub struct Forge {
+  #[serde(rename = "type")]
+  pub kind: ForgeKind,
+  // like https://api.github.com or https://gitea.example.com/api/v1
+  pub api_base: String,
+  pub token: String,
+  // builds with an origin starting with one of these are reported here. the
+  // rest of the origin is the repository's path on the forge
+  pub origins: Vec<String>,
+}
+
+// branches of a repository to build whenever they move, for repositories that
+// aren't a project
+#[derive(Debug, Deserialize, Clone)]
+pub struct PollTarget {
+  pub origin: String,
+  pub branches: Vec<String>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -31,8 +68,125 @@ pub struct Config {
   pub scm_path: PathBuf,
 
   pub publish: Publish,
+  // alternatives to `publish` that a repository's .starfish.toml can pick by name
+  #[serde(default)]
+  pub publish_targets: HashMap<String, Publish>,
 
   pub database_url: String,
+
+  // must be unique among all workers sharing a database
+  #[serde(default = "default_worker_id")]
+  pub worker_id: String,
+  // builds owned by a worker that hasn't checked in for this long are up for
+  // grabs
+  #[serde(default = "default_lease_secs")]
+  pub lease_secs: u32,
+  // how many times a build that was interrupted gets put back in the queue
+  #[serde(default = "default_max_retries")]
+  pub max_retries: u32,
+  // builds beyond this limit wait in the queue
+  #[serde(default = "default_max_concurrent_builds")]
+  pub max_concurrent_builds: usize,
+  // wall-clock limit for each nix-build/nix-shell invocation, unless the build
+  // asks for something else
+  #[serde(default = "default_build_timeout_secs")]
+  pub build_timeout_secs: u64,
+  // nix options and environment variables a .starfish.toml may set for its
+  // inputs. these should match the web server's
+  #[serde(default)]
+  pub allowed_options: Vec<String>,
+  #[serde(default)]
+  pub allowed_env: Vec<String>,
+  // how often to check `poll` and the projects' poll_branches for new commits.
+  // this worker doesn't poll at all if it's not set
+  pub poll_interval_secs: Option<u64>,
+  #[serde(default)]
+  pub poll: Vec<PollTarget>,
+  // where the web frontend is, for linking to builds
+  pub public_url: Option<String>,
+  #[serde(default)]
+  pub forges: Vec<Forge>,
+  // how many times to try reporting a status or delivering a webhook before
+  // giving up on it
+  #[serde(default = "default_max_report_attempts")]
+  pub max_report_attempts: i32,
+}
+
+impl Config {
+  pub fn git_ssh_command(&self) -> String {
+    self.git_ssh_key.as_ref().map_or_else(
+      || "ssh".to_string(),
+      |k| {
+        format!(
+          "ssh -i {} -o IdentitiesOnly=yes -o StrictHostKeyChecking=no",
+          k.display()
+        )
+      },
+    )
+  }
+
+  // every system at least one of the builders can build for
+  pub fn supported_systems(&self) -> Vec<String> {
+    let mut systems = vec![];
+    for builder in &self.builders {
+      // `@file` means the builders are listed in a file, one per line
+      let spec = match builder.strip_prefix('@') {
+        Some(path) => std::fs::read_to_string(path.trim()).unwrap_or_default(),
+        None => builder.clone(),
+      };
+      for machine in spec.split([';', '\n']) {
+        let mut fields = machine.split_whitespace();
+        match fields.next() {
+          None => continue,
+          Some(uri) if uri.starts_with('#') => continue,
+          Some(_) => {}
+        }
+        match fields.next() {
+          Some(s) if s != "-" => systems.extend(s.split(',').map(str::to_string)),
+          // nix assumes the builder is the same kind of machine as we are
+          _ => systems.push(local_system()),
+        }
+      }
+    }
+    systems.sort();
+    systems.dedup();
+    systems
+  }
+}
+
+fn local_system() -> String {
+  let os = match std::env::consts::OS {
+    "macos" => "darwin",
+    os => os,
+  };
+  format!("{}-{os}", std::env::consts::ARCH)
+}
+
+fn default_worker_id() -> String {
+  nix::unistd::gethostname()
+    .expect("unable to determine hostname, please set worker_id")
+    .to_string_lossy()
+    .into_owned()
+}
+
+fn default_lease_secs() -> u32 {
+  60
+}
+
+fn default_max_retries() -> u32 {
+  2
+}
+
+fn default_max_concurrent_builds() -> usize {
+  4
+}
+
+fn default_build_timeout_secs() -> u64 {
+  4 * 60 * 60
+}
+
+fn default_max_report_attempts() -> i32 {
+  6
 }
 
 fn default_target_platforms() -> Vec<Cow<'static, str>> {
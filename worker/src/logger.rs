@@ -1,7 +1,15 @@
 use std::fmt::Display;
 use std::fs::File;
-use std::io::Write;
-use std::process::{Command, ExitStatus, Output};
+use std::io::{Error, ErrorKind, Write};
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, ExitStatus, Output, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::time::Duration;
+
+use nix::sys::signal::{killpg, Signal};
+use nix::unistd::Pid;
 
 /// This struct exists so you can execute commands that pipe to a file while
 /// also returning the output to the calling process (us). It only really works
@@ -9,36 +17,115 @@ use std::process::{Command, ExitStatus, Output};
 /// nix-build does that.
 pub struct Logger {
   fd: File,
+  cancel: Arc<Cancel>,
+}
+
+/// Shared between a running build and the worker so the worker can kill
+/// whatever the build is currently executing. Every child is started as the
+/// leader of its own process group, which means killing the group also takes
+/// out anything nix-build or nix-shell spawned in turn.
+#[derive(Default)]
+pub struct Cancel {
+  canceled: AtomicBool,
+  pgid: AtomicI32,
+}
+
+impl Cancel {
+  pub fn cancel(&self) {
+    self.canceled.store(true, Ordering::SeqCst);
+    self.kill();
+  }
+
+  pub fn is_canceled(&self) -> bool {
+    self.canceled.load(Ordering::SeqCst)
+  }
+
+  fn kill(&self) {
+    let pgid = self.pgid.load(Ordering::SeqCst);
+    if pgid != 0 {
+      // the group may have exited on its own in the meantime, which is fine
+      let _ = killpg(Pid::from_raw(pgid), Signal::SIGKILL);
+    }
+  }
 }
 
 impl From<File> for Logger {
   fn from(fd: File) -> Self {
-    Self { fd }
+    Self {
+      fd,
+      cancel: Arc::default(),
+    }
   }
 }
 
 impl Logger {
+  pub fn cancel_handle(&self) -> Arc<Cancel> {
+    Arc::clone(&self.cancel)
+  }
+
   pub fn exec(&mut self, cmd: &mut Command) -> std::io::Result<ExitStatus> {
     self.debug(cmd)?;
     cmd
       .env("PATH", std::env::var_os("PATH").expect("PATH not set"))
       .stderr(self.fd.try_clone()?)
-      .stdout(self.fd.try_clone()?)
-      .status()
+      .stdout(self.fd.try_clone()?);
+    let mut child = self.spawn(cmd)?;
+    let status = child.wait();
+    self.cancel.pgid.store(0, Ordering::SeqCst);
+    status
   }
 
   pub fn output(&mut self, cmd: &mut Command) -> std::io::Result<Output> {
     self.debug(cmd)?;
-    let out = cmd
+    cmd
       .env("PATH", std::env::var_os("PATH").expect("PATH not set"))
+      .stdin(Stdio::null())
       .stderr(self.fd.try_clone()?)
-      .output()?;
+      .stdout(Stdio::piped());
+    let child = self.spawn(cmd)?;
+    let out = child.wait_with_output();
+    self.cancel.pgid.store(0, Ordering::SeqCst);
+    let out = out?;
 
     self.fd.write_all(&out.stdout)?;
 
     Ok(out)
   }
 
+  /// Like `output`, but kills the command's process group if it's still running
+  /// after `timeout`. Returns `None` if that happened.
+  pub fn output_timeout(
+    &mut self,
+    cmd: &mut Command,
+    timeout: Duration,
+  ) -> std::io::Result<Option<Output>> {
+    self.debug(cmd)?;
+    cmd
+      .env("PATH", std::env::var_os("PATH").expect("PATH not set"))
+      .stdin(Stdio::null())
+      .stderr(self.fd.try_clone()?)
+      .stdout(Stdio::piped());
+    let child = self.spawn(cmd)?;
+    let pgid = Pid::from_raw(child.id() as i32);
+    let (done, wait_done) = mpsc::channel::<()>();
+    let watchdog = std::thread::spawn(move || match wait_done.recv_timeout(timeout) {
+      Err(RecvTimeoutError::Timeout) => {
+        let _ = killpg(pgid, Signal::SIGKILL);
+        true
+      }
+      _ => false,
+    });
+    let out = child.wait_with_output();
+    let _ = done.send(());
+    let timed_out = watchdog.join().unwrap_or(false);
+    self.cancel.pgid.store(0, Ordering::SeqCst);
+    let out = out?;
+
+    self.fd.write_all(&out.stdout)?;
+
+    Ok((!timed_out).then_some(out))
+  }
+
   pub fn log<D: Display>(&mut self, message: D) -> std::io::Result<()> {
     writeln!(self.fd, "{message}")
   }
@@ -47,6 +134,19 @@ impl Logger {
     self.log(format!("$ {cmd}"))
   }
 
+  fn spawn(&mut self, cmd: &mut Command) -> std::io::Result<Child> {
+    if self.cancel.is_canceled() {
+      return Err(Error::new(ErrorKind::Interrupted, "build was canceled"));
+    }
+    let child = cmd.process_group(0).spawn()?;
+    self.cancel.pgid.store(child.id() as i32, Ordering::SeqCst);
+    // a cancel that raced with spawn() won't have seen the new pgid
+    if self.cancel.is_canceled() {
+      self.cancel.kill();
+    }
+    Ok(child)
+  }
+
   fn debug(&mut self, cmd: &Command) -> std::io::Result<()> {
     write!(self.fd, "$ {}", cmd.get_program().to_string_lossy())?;
     for arg in cmd.get_args() {
@@ -2,158 +2,423 @@
 
 mod cfg;
 mod logger;
+mod notifier;
+mod poller;
 mod scripts;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::fs::File;
 use std::io::Write;
-use std::path::Display;
-use std::process::Command;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Display, Path, PathBuf};
+use std::process::{Command, Output};
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{Context, Result};
 use askama::Template;
 use cfg::{Config, Publish};
 use chrono::Utc;
-use common::{Build, BuildStatus, Input};
-use futures_util::StreamExt;
-use log::info;
-use logger::Logger;
+use common::{is_commit_hash, Build, BuildDef, BuildStatus, FailureKind, Input, OutputStatus};
+use log::{info, warn};
+use logger::{Cancel, Logger};
 use nix::sys::statvfs::{statvfs, Statvfs};
 use sha1::{Digest, Sha1};
 use sqlx::postgres::PgListener;
-use sqlx::PgPool;
+use sqlx::types::Json;
+use sqlx::{Executor, PgPool, Postgres};
 use tempfile::TempDir;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::sync::Notify;
 use tokio::task::JoinHandle;
 
 struct Worker<'a> {
   cfg: Arc<Config>,
-  jobs: HashMap<i32, JoinHandle<()>>,
+  jobs: HashMap<i32, Job>,
+  // every job sends its build ID here once it's done, which frees up a slot
+  finished: UnboundedSender<i32>,
   fsdata: Statvfs,
+  // what the builders can build for, claimed builds can't ask for anything else
+  systems: Vec<String>,
   db: &'a PgPool,
 }
 
+struct Job {
+  handle: JoinHandle<()>,
+  cancel: Arc<Cancel>,
+  // drops the build at its next await, like aborting a task would
+  stopped: Arc<Notify>,
+  log_file: String,
+}
+
 impl<'a> Worker<'a> {
-  fn new(cfg: Config, db: &'a PgPool) -> Result<Self> {
+  fn new(cfg: Config, db: &'a PgPool) -> Result<(Self, UnboundedReceiver<i32>)> {
     let fsdata = statvfs("/nix/store")?;
+    let (finished, finished_rx) = mpsc::unbounded_channel();
+    let systems = cfg.supported_systems();
 
-    Ok(Self {
-      cfg: Arc::new(cfg),
-      jobs: HashMap::new(),
-      fsdata,
-      db,
-    })
+    Ok((
+      Self {
+        cfg: Arc::new(cfg),
+        jobs: HashMap::new(),
+        finished,
+        fsdata,
+        systems,
+        db,
+      },
+      finished_rx,
+    ))
   }
 
-  async fn run(mut self) -> Result<()> {
-    info!("checking for old unfinished builds");
-    // TODO: this only starts builds that never got picked up by the worker. it
-    // should restart builds that started running but got reaped or OOM'd or
-    // whatever. but that logic is a lot harder.
-    let mut unbuilt_builds = sqlx::query!(
-      r#"SELECT id FROM builds WHERE status IN ($1,$2,$3)"#,
-      BuildStatus::Queued as _,
-      BuildStatus::Building as _,
-      BuildStatus::Uploading as _
+  async fn run(mut self, mut finished: UnboundedReceiver<i32>) -> Result<()> {
+    info!(
+      "registering as worker '{}', building for {}",
+      self.cfg.worker_id,
+      self.systems.join(", ")
+    );
+    sqlx::query!(
+      "INSERT INTO workers (id, max_builds, systems) VALUES ($1, $2, $3) ON CONFLICT (id) DO \
+       UPDATE SET started_at = now(), last_heartbeat = now(), max_builds = $2, systems = $3",
+      &self.cfg.worker_id,
+      self.cfg.max_concurrent_builds as i32,
+      &self.systems
     )
-    .fetch(self.db);
+    .execute(self.db)
+    .await?;
 
-    // TODO: rewrite this with try_for_each() or something (the types are annoying)
-    while let Some(x) = unbuilt_builds.next().await.transpose()? {
-      self.handle("build_restarted", x.id).await?;
+    tokio::spawn(heartbeat(self.db.clone(), Arc::clone(&self.cfg)));
+    if let Some(secs) = self.cfg.poll_interval_secs {
+      info!("polling for new commits every {secs}s");
+      tokio::spawn(poller::poll(
+        self.db.clone(),
+        Arc::clone(&self.cfg),
+        Duration::from_secs(secs),
+      ));
     }
+    if !self.cfg.forges.is_empty() {
+      tokio::spawn(notifier::report_statuses(
+        self.db.clone(),
+        Arc::clone(&self.cfg),
+      ));
+    }
+    tokio::spawn(notifier::deliver_webhooks(
+      self.db.clone(),
+      Arc::clone(&self.cfg),
+    ));
 
-    info!("waiting for build notifications");
+    // start listening before looking for unclaimed builds, otherwise anything
+    // queued in between would be missed
     let mut listener = PgListener::connect_with(self.db).await?;
     listener
       .listen_all(["build_queued", "build_restarted", "build_canceled"])
       .await?;
+
+    // the first tick completes immediately, which takes care of anything left
+    // over from before we started
+    let mut orphan_check = tokio::time::interval(Duration::from_secs(self.cfg.lease_secs.into()));
+
+    info!("waiting for build notifications");
+    loop {
+      tokio::select! {
+        notif = listener.recv() => {
+          let notif = notif?;
+          let build_id = notif.payload().parse::<i32>()?;
+          self.handle(notif.channel(), build_id).await?;
+        }
+        Some(build_id) = finished.recv() => {
+          self.jobs.remove(&build_id);
+          self.fill_slots().await?;
+        }
+        _ = orphan_check.tick() => {
+          self.reap().await?;
+          self.fill_slots().await?;
+        }
+      }
+    }
+  }
+
+  // a build can only be running if some worker is actually running it. that
+  // rules out builds claimed by a worker that stopped checking in, and builds
+  // we claimed ourselves but don't have a job for (because we were restarted
+  // or OOM-killed in the middle). those runs are marked as failed and the build
+  // goes back in the queue, unless it has been retried too many times already.
+  async fn reap(&mut self) -> Result<()> {
+    self.jobs.retain(|_, job| !job.handle.is_finished());
+    let running = self.jobs.keys().copied().collect::<Vec<_>>();
+
+    let reaped = sqlx::query!(
+      "UPDATE builds b SET status = CASE WHEN b.retries < $5 THEN $1::build_status ELSE \
+       $2::build_status END, retries = CASE WHEN b.retries < $5 THEN b.retries + 1 ELSE b.retries \
+       END, finished_at = CASE WHEN b.retries < $5 THEN NULL ELSE now() END, failure_kind = CASE \
+       WHEN b.retries < $5 THEN NULL ELSE $9::failure_kind END, error_msg = format('Build was \
+       interrupted because worker %s went away (attempt %s of %s)', coalesce(s.worker_id, \
+       '(unknown)'), b.retries + 1, $5 + 1) FROM (SELECT b2.id, b2.worker_id FROM builds b2 LEFT \
+       JOIN workers w ON w.id = b2.worker_id WHERE b2.status IN ($3, $4) AND ((b2.worker_id = $6 \
+       AND b2.id <> ALL($7)) OR (b2.worker_id IS DISTINCT FROM $6 AND (w.last_heartbeat IS NULL \
+       OR w.last_heartbeat < now() - $8::int * interval '1 second'))) FOR UPDATE OF b2 SKIP \
+       LOCKED) s WHERE b.id = s.id RETURNING b.id, b.status as \"status: BuildStatus\"",
+      BuildStatus::Queued as _,
+      BuildStatus::Failed as _,
+      BuildStatus::Building as _,
+      BuildStatus::Uploading as _,
+      self.cfg.max_retries as i32,
+      &self.cfg.worker_id,
+      &running,
+      self.cfg.lease_secs as i32,
+      FailureKind::Error as _
+    )
+    .fetch_all(self.db)
+    .await?;
+
+    sqlx::query!(
+      "UPDATE build_attempts a SET status = $2, finished_at = now(), error_msg = b.error_msg, \
+       failure_kind = $3 FROM builds b WHERE b.id = a.build_id AND a.build_id = ANY($1) AND \
+       a.finished_at IS NULL",
+      &reaped.iter().map(|r| r.id).collect::<Vec<_>>(),
+      BuildStatus::Failed as _,
+      FailureKind::Error as _
+    )
+    .execute(self.db)
+    .await?;
+    // requeued builds start over with new outputs anyway
+    skip_unfinished_outputs(self.db, &reaped.iter().map(|r| r.id).collect::<Vec<_>>()).await?;
+
+    let mut requeued = vec![];
+    for r in reaped {
+      info!("build {} was interrupted, now {:?}", r.id, r.status);
+      if matches!(r.status, BuildStatus::Queued) {
+        requeued.push(r.id.to_string());
+      }
+    }
+
+    // let everyone know, in case we're too busy to take them
+    sqlx::query!(
+      "SELECT pg_notify($1, id) FROM UNNEST($2::text[]) id",
+      "build_queued",
+      &requeued
+    )
+    .execute(self.db)
+    .await?;
+
+    Ok(())
+  }
+
+  // claims queued builds, oldest first, until we're out of slots or there's
+  // nothing left to claim. anything we don't get to stays in the queue for
+  // later, or for another worker.
+  async fn fill_slots(&mut self) -> Result<()> {
     loop {
-      let notif = listener.recv().await?;
-      let build_id = notif.payload().parse::<i32>()?;
-      self.handle(notif.channel(), build_id).await?;
+      let running = self
+        .jobs
+        .values()
+        .filter(|j| !j.handle.is_finished())
+        .count();
+      if running >= self.cfg.max_concurrent_builds {
+        info!("all {} build slots are in use", running);
+        return Ok(());
+      }
+
+      let Some(build_info) = self.claim_next().await? else {
+        return Ok(());
+      };
+      self.build(build_info).await?;
     }
   }
 
   async fn handle(&mut self, channel: &str, build_id: i32) -> Result<()> {
     info!("got {}: '{}'", channel, build_id);
-    if channel == "build_restarted" {
-      // delete evidence of old builds so they don't clog up the UI
+    match channel {
+      "build_canceled" => self.cancel(build_id).await?,
+      "build_restarted" => {
+        if let Some(mut job) = self.jobs.remove(&build_id) {
+          job.stop().await;
+        }
+      }
+      _ => {}
+    }
+    self.fill_slots().await
+  }
+
+  async fn cancel(&mut self, build_id: i32) -> Result<()> {
+    if let Some(mut job) = self.jobs.remove(&build_id) {
+      job.stop().await;
+
+      if let Some(build_info) = Build::get(build_id, self.db).await? {
+        let cleanup: Result<()> = try {
+          let log_filepath = self.cfg.log_path.join(&job.log_file);
+          let mut logger = Logger::from(File::options().append(true).open(log_filepath)?);
+          logger.log("Build canceled.")?;
+          let scm_dir = self.scm_dir(&build_info.origin);
+          if scm_dir.join(build_tag(build_id)).exists() {
+            remove_worktree(&mut logger, &scm_dir, &build_tag(build_id))?;
+          }
+        };
+        if let Err(e) = cleanup {
+          warn!(
+            "unable to clean up after canceled build {}: {:?}",
+            build_id, e
+          );
+        }
+      }
+    }
+
+    // a build that already finished can't be canceled
+    let mut tx = self.db.begin().await?;
+    let canceled = sqlx::query!(
+      "UPDATE builds SET status = $2, finished_at = $3 WHERE id = $1 AND status IN ($4, $5, $6)",
+      build_id,
+      BuildStatus::Canceled as _,
+      Utc::now(),
+      BuildStatus::Queued as _,
+      BuildStatus::Building as _,
+      BuildStatus::Uploading as _
+    )
+    .execute(&mut *tx)
+    .await?;
+    if canceled.rows_affected() > 0 {
       sqlx::query!(
-        "DELETE FROM outputs WHERE id in (select outputs.id from outputs inner join inputs on \
-         outputs.input_id = inputs.id where inputs.build_id = $1)",
-        build_id
+        "UPDATE build_attempts SET status = $2, finished_at = $3 WHERE build_id = $1 AND \
+         finished_at IS NULL",
+        build_id,
+        BuildStatus::Canceled as _,
+        Utc::now()
       )
-      .execute(self.db)
+      .execute(&mut *tx)
       .await?;
+      skip_unfinished_outputs(&mut *tx, &[build_id]).await?;
+    }
+    tx.commit().await?;
 
-      // TODO: we really should keep old logs
-      let log_filepath = self.cfg.log_path.join(format!("{build_id}.log"));
-      if log_filepath.exists() {
-        let _ = File::create(log_filepath)?;
-      }
+    Ok(())
+  }
 
-      if let Some(jh) = self.jobs.remove(&build_id) {
-        jh.abort();
-      }
-    }
-    self.build(build_id).await
+  fn scm_dir(&self, origin: &str) -> PathBuf {
+    let mut s = Sha1::new();
+    s.update(origin);
+    self
+      .cfg
+      .scm_path
+      .join(base16ct::lower::encode_string(&s.finalize()))
   }
 
-  async fn build(&mut self, build_id: i32) -> Result<()> {
-    let build_info = sqlx::query_as!(
-      Build,
-      "SELECT id, origin, rev, created_at, finished_at, error_msg, status as \"status: _\" FROM \
-       builds WHERE id = $1",
+  async fn build(&mut self, build_info: Build) -> Result<()> {
+    let build_id = build_info.id;
+
+    // outputs only describe the latest attempt. everything else about old
+    // attempts, including their logs, is kept around in build_attempts
+    sqlx::query!(
+      "DELETE FROM outputs WHERE id in (select outputs.id from outputs inner join inputs on \
+       outputs.input_id = inputs.id where inputs.build_id = $1)",
       build_id
     )
-    .fetch_optional(self.db)
+    .execute(self.db)
     .await?;
-    let Some(r) = build_info else {
-      info!("build {} has gone missing, doing nothing", build_id);
-      return Ok(());
-    };
-    self.build_impl(r).await
+
+    // jobsets get evaluated again, they might have changed in the meantime
+    sqlx::query!(
+      "DELETE FROM inputs WHERE build_id = $1 AND parent_id IS NOT NULL",
+      build_id
+    )
+    .execute(self.db)
+    .await?;
+
+    // anything a previous attempt didn't get to finish is over now
+    sqlx::query!(
+      "UPDATE build_attempts SET status = $2, finished_at = now() WHERE build_id = $1 AND \
+       finished_at IS NULL",
+      build_id,
+      BuildStatus::Canceled as _
+    )
+    .execute(self.db)
+    .await?;
+
+    let attempt = sqlx::query!(
+      "INSERT INTO build_attempts (build_id, attempt, worker_id, log_file) SELECT $1, n, $2, \
+       format('%s.%s.log', $1, n) FROM (SELECT coalesce(max(attempt), 0) + 1 AS n FROM \
+       build_attempts WHERE build_id = $1) a RETURNING id, log_file",
+      build_id,
+      &self.cfg.worker_id
+    )
+    .fetch_one(self.db)
+    .await?;
+
+    self
+      .build_impl(build_info, attempt.id, attempt.log_file)
+      .await
   }
 
-  async fn build_impl(&mut self, mut build_info: Build) -> Result<()> {
-    let log_filepath = self.cfg.log_path.join(format!("{}.log", build_info.id));
+  // takes the most important build nobody is working on yet, oldest first. every
+  // worker hears every notification, so this is what keeps two of them from
+  // running the same build. builds asking for systems we can't build for are
+  // left for someone else.
+  async fn claim_next(&self) -> Result<Option<Build>> {
+    Ok(
+      sqlx::query_as!(
+        Build,
+        "UPDATE builds SET status = $1, worker_id = $2 WHERE id = (SELECT id FROM builds b WHERE \
+         status = $3 AND (b.systems IS NULL OR b.systems <@ $4) AND NOT EXISTS (SELECT 1 FROM \
+         inputs i WHERE i.build_id = b.id AND NOT i.systems <@ $4) ORDER BY priority DESC, \
+         created_at, id LIMIT 1 FOR UPDATE OF b SKIP LOCKED) RETURNING id, origin, rev, \
+         created_at, finished_at, error_msg, status as \"status: _\", worker_id, priority, \
+         timeout_secs, failure_kind as \"failure_kind: _\", systems, definition as \"definition: \
+         _\", project_id, branch, publish, submitted_by",
+        BuildStatus::Building as _,
+        &self.cfg.worker_id,
+        BuildStatus::Queued as _,
+        &self.systems
+      )
+      .fetch_optional(self.db)
+      .await?,
+    )
+  }
+
+  async fn build_impl(
+    &mut self,
+    mut build_info: Build,
+    attempt_id: i32,
+    log_file: String,
+  ) -> Result<()> {
+    let log_filepath = self.cfg.log_path.join(&log_file);
     let bid = build_info.id;
+    let worker_id = self.cfg.worker_id.clone();
 
-    let all_inputs = sqlx::query_as!(Input, "SELECT * FROM inputs WHERE build_id = $1", bid)
-      .fetch_all(self.db)
-      .await?;
+    let mut all_inputs = sqlx::query_as!(
+      Input,
+      "SELECT * FROM inputs WHERE build_id = $1 AND parent_id IS NULL",
+      bid
+    )
+    .fetch_all(self.db)
+    .await?;
 
     std::fs::create_dir_all(log_filepath.parent().unwrap())?;
     let mut logger = Logger::from(File::create(&log_filepath)?);
-
-    let mut s = Sha1::new();
-    s.update(&build_info.origin);
+    let cancel = logger.cancel_handle();
 
     macro_rules! status {
       ($stat:expr, $executor:expr) => {
-        sqlx::query!(
-          "UPDATE builds SET status = $2 WHERE id = $1",
+        status!($stat, FailureKind::Error, $executor)
+      };
+      ($stat:expr, $kind:expr, $executor:expr) => {
+        set_status(
+          $executor,
           bid,
-          $stat as _
+          attempt_id,
+          &worker_id,
+          $stat,
+          Some($kind),
+          None,
         )
-        .execute($executor)
         .await?
       };
     }
 
-    status!(BuildStatus::Building, self.db);
-
     // create a bare repository in $scm_path, then add a worktree pointing to the
     // right commit. this way we can run builds for multiple commits at the same
     // time. everything from checking path existence to creating the new worktree is
     // "synchronous" (in this process, not across processes, no filesystem locking
     // or anything) and then nix-store and nix-instantiate are called in a separate
     // thread since they make up the bulk of the runtime.
-    let scm_dir = self
-      .cfg
-      .scm_path
-      .join(base16ct::lower::encode_string(&s.finalize()));
+    let scm_dir = self.scm_dir(&build_info.origin);
     if !scm_dir.exists() {
       std::fs::create_dir_all(&scm_dir)?;
       logger.exec(
@@ -175,15 +440,7 @@ impl<'a> Worker<'a> {
       }
     }
 
-    let git_ssh_cmd = self.cfg.git_ssh_key.as_ref().map_or_else(
-      || "ssh".to_string(),
-      |k| {
-        format!(
-          "ssh -i {} -o IdentitiesOnly=yes -o StrictHostKeyChecking=no",
-          k.display()
-        )
-      },
-    );
+    let git_ssh_cmd = self.cfg.git_ssh_command();
 
     if !logger
       .exec(
@@ -239,7 +496,7 @@ impl<'a> Worker<'a> {
       build_info.rev = real_hash;
     }
 
-    let build_tag = format!("__starfish_build_{}", build_info.id);
+    let build_tag = build_tag(build_info.id);
     logger.exec(
       Command::new("git")
         .args(["worktree", "remove", "--force"])
@@ -268,6 +525,96 @@ impl<'a> Worker<'a> {
       return Ok(());
     }
 
+    // builds queued without any inputs get them from the repository instead.
+    // once that's happened the inputs stay put, so restarting the build builds
+    // the same thing again
+    if all_inputs.is_empty() && build_info.definition.is_none() {
+      let (definition, args) =
+        match self.load_definition(&scm_dir.join(&build_tag).join(".starfish.toml")) {
+          Ok(Some(def)) if !def.0.inputs.is_empty() => def,
+          // there'd be nothing to build, which shouldn't count as a success
+          result => {
+            let msg = match result {
+              Err(msg) => msg,
+              Ok(None) => "no inputs were given and there's no .starfish.toml".to_string(),
+              Ok(Some(_)) => ".starfish.toml doesn't have any inputs".to_string(),
+            };
+            logger.log(&msg)?;
+            set_status(
+              self.db,
+              bid,
+              attempt_id,
+              &worker_id,
+              BuildStatus::Failed,
+              Some(FailureKind::Error),
+              Some(&msg),
+            )
+            .await?;
+            return Ok(());
+          }
+        };
+
+      let mut tx = self.db.begin().await?;
+      for (input, (args, env)) in definition.inputs.iter().zip(args) {
+        all_inputs.push(
+          sqlx::query_as!(
+            Input,
+            "INSERT INTO inputs (build_id, path, jobset, systems, attr, args, env) VALUES ($1, \
+             $2, $3, $4, $5, $6, $7) RETURNING *",
+            bid,
+            input.path.trim(),
+            input.jobset,
+            input.systems.as_deref(),
+            input.attr.as_deref(),
+            &args,
+            &env
+          )
+          .fetch_one(&mut *tx)
+          .await?,
+        );
+      }
+      // anything given when the build was queued wins
+      build_info = sqlx::query_as!(
+        Build,
+        "UPDATE builds SET definition = $2, systems = coalesce(systems, $3), timeout_secs = \
+         coalesce(timeout_secs, $4), publish = coalesce(publish, $5) WHERE id = $1 RETURNING id, \
+         origin, rev, created_at, finished_at, error_msg, status as \"status: _\", worker_id, \
+         priority, timeout_secs, failure_kind as \"failure_kind: _\", systems, definition as \
+         \"definition: _\", project_id, branch, publish, submitted_by",
+        bid,
+        Json(&definition) as _,
+        definition.systems.as_deref(),
+        definition.timeout_secs,
+        definition.publish.as_deref()
+      )
+      .fetch_one(&mut *tx)
+      .await?;
+      tx.commit().await?;
+    }
+
+    let publish = match build_info.publish.as_deref() {
+      None => self.cfg.publish.clone(),
+      Some("none") => Publish::None,
+      Some(name) => match self.cfg.publish_targets.get(name) {
+        Some(publish) => publish.clone(),
+        None => {
+          let msg = format!("there's no publish target called {name:?}");
+          logger.log(&msg)?;
+          set_status(
+            self.db,
+            bid,
+            attempt_id,
+            &worker_id,
+            BuildStatus::Failed,
+            Some(FailureKind::Error),
+            Some(&msg),
+          )
+          .await?;
+          return Ok(());
+        }
+      },
+    };
+
     // clone the pool instance so it satisfies 'static
     let finalizer_conn = self.db.clone();
     let cfg = Arc::clone(&self.cfg);
@@ -278,7 +625,24 @@ impl<'a> Worker<'a> {
     // stop GC once the disk is half empty
     let max_free = filesystem_bytes / 2;
 
-    let jh = tokio::spawn(async move {
+    let timeout = Duration::from_secs(
+      build_info
+        .timeout_secs
+        .map_or(self.cfg.build_timeout_secs, |t| t as u64),
+    );
+
+    // set by whoever queued the build, if they wanted something specific
+    let build_systems = build_info.systems.clone().unwrap_or_else(|| {
+      self
+        .cfg
+        .target_platforms
+        .iter()
+        .map(|s| s.to_string())
+        .collect()
+    });
+
+    let finished = self.finished.clone();
+    let build = async move {
       let build_err: Result<()> = try {
         // the global config in NIX_CONF_DIR is totally ignored if a user-level config
         // exists. in the docker image, that's not a problem, but while testing locally
@@ -286,7 +650,11 @@ impl<'a> Worker<'a> {
         let nix_superconf_dir = TempDir::new()?;
 
         let post_build_path = nix_superconf_dir.path().join("post-build.sh");
-        Self::setup_secrets(&cfg.publish, File::create(&post_build_path)?)?;
+        Self::setup_secrets(
+          &publish,
+          nix_superconf_dir.path(),
+          File::create(&post_build_path)?,
+        )?;
 
         std::fs::create_dir(nix_superconf_dir.path().join("nix"))?;
         let mut nix_conf = File::create(nix_superconf_dir.path().join("nix").join("nix.conf"))?;
@@ -310,93 +678,431 @@ impl<'a> Worker<'a> {
 
         logger.exec(Command::new("chmod").arg("+x").arg(post_build_path))?;
 
-        for input in all_inputs {
-          for target_system in &cfg.target_platforms {
-            let worktree_dir = scm_dir.join(&build_tag);
-            let store_path = logger.output(
-              guess_build_command(&input.path)
-                .args(["--argstr", "system", target_system])
-                .arg("--keep-going")
-                .env_clear()
-                .env("NIX_BUILD_SHELL", &cfg.build_shell)
-                .env("GIT_SSH_COMMAND", &git_ssh_cmd)
-                .env("HOME", nix_superconf_dir.path())
-                .current_dir(&worktree_dir),
-            )?;
-
-            if !store_path.status.success() {
-              logger.log(format!("build exited with status {}", store_path.status))?;
-              status!(BuildStatus::Failed, &finalizer_conn);
-              return;
+        let worktree_dir = scm_dir.join(&build_tag);
+        // `env` is the input's own environment, which the web server only lets
+        // through if it's allowed
+        let nix_env = |cmd: &mut Command, env: &[String]| {
+          cmd
+            .env_clear()
+            .env("NIX_BUILD_SHELL", &cfg.build_shell)
+            .env("GIT_SSH_COMMAND", &git_ssh_cmd)
+            .env("HOME", nix_superconf_dir.path())
+            .envs(env.iter().filter_map(|e| e.split_once('=')))
+            .current_dir(&worktree_dir);
+        };
+
+        // work out every (input, system) pair up front so they can all be
+        // queued at once. `failures` collects the kind of every pair that
+        // failed, here or while building
+        let mut queue = all_inputs.into_iter().collect::<VecDeque<_>>();
+        let mut failures = vec![];
+        // (input, system, command that evaluates to the .drv, command that builds it)
+        let mut plan = vec![];
+        while let Some(input) = queue.pop_front() {
+          let path = path_arg(&input.path);
+          let systems = input
+            .systems
+            .clone()
+            .unwrap_or_else(|| build_systems.clone());
+          if input.jobset {
+            let mut eval = Command::new("nix");
+            eval.args(["eval", "--raw", "--no-write-lock-file"]);
+            if path.contains('#') {
+              eval.arg(&path);
+            } else {
+              eval.arg("--file").arg(&path);
+              eval.args(&input.attr);
             }
-            let output_path = String::from_utf8_lossy(&store_path.stdout)
-              .trim_end()
-              .to_string();
+            eval.args(&input.args).args(["--apply", LIST_JOBS]);
+            nix_env(&mut eval, &input.env);
+            let jobs = match logger.output_timeout(&mut eval, timeout)? {
+              Some(jobs) if jobs.status.success() => jobs,
+              jobs => {
+                let kind = eval_failure(&mut logger, jobs, timeout)?;
+                // none of its jobs are known, so it fails for every system it
+                // would have been built for
+                fail_outputs(&finalizer_conn, input.id, &systems, kind).await?;
+                failures.extend(systems.iter().map(|_| kind));
+                continue;
+              }
+            };
 
-            sqlx::query!(
-              "INSERT INTO outputs (input_id, system, store_path) VALUES ($1, $2, $3)",
+            let (systems, attrs): (Vec<_>, Vec<_>) = String::from_utf8_lossy(&jobs.stdout)
+              .lines()
+              .filter_map(|l| l.split_once(' '))
+              .filter(|(system, _)| {
+                // jobsets can't be told which systems to evaluate for, so the
+                // best we can do is leave out the jobs nobody asked for, or
+                // that we wouldn't build by default
+                systems.iter().any(|s| s == system)
+              })
+              .map(|(system, attr)| {
+                let attr = match &input.attr {
+                  Some(parent) => join_attr(parent, attr),
+                  None => attr.to_string(),
+                };
+                (system.to_string(), attr)
+              })
+              .unzip();
+            let children = sqlx::query_as!(
+              Input,
+              "INSERT INTO inputs (build_id, path, parent_id, system, attr, args, env) SELECT $1, \
+               $2, $3, *, $6, $7 FROM UNNEST($4::text[], $5::text[]) RETURNING *",
+              bid,
+              &input.path,
               input.id,
-              target_system,
-              output_path
+              &systems,
+              &attrs,
+              &input.args,
+              &input.env
             )
-            .execute(&finalizer_conn)
+            .fetch_all(&finalizer_conn)
             .await?;
+            logger.log(format!("found {} jobs in {}", children.len(), input.path))?;
+            queue.extend(children);
+            continue;
+          }
+
+          if let (Some(system), Some(attr)) = (&input.system, &input.attr) {
+            // jobs know which system they're for already
+            if path.contains('#') {
+              let installable = join_attr(&path, attr);
+              let eval = nix_eval_command([installable.clone()]);
+              let build = nix_build_command([installable]);
+              let system = system.clone();
+              plan.push((input, system, eval, build));
+            } else {
+              let mut eval = Command::new("nix-instantiate");
+              eval.arg(&path).args(["-A", attr]);
+              let mut build = Command::new("nix-build");
+              build.arg(&path).args(["-A", attr]).arg("--keep-going");
+              let system = system.clone();
+              plan.push((input, system, eval, build));
+            }
+          } else if let Some((flake, attr)) = path.split_once('#') {
+            let Some(targets) = flake_targets(attr, &systems) else {
+              logger.log(format!(
+                "unable to tell which system {} should be built for",
+                input.path
+              ))?;
+              fail_outputs(&finalizer_conn, input.id, &systems, FailureKind::Evaluation).await?;
+              failures.extend(systems.iter().map(|_| FailureKind::Evaluation));
+              continue;
+            };
+            for target in targets {
+              let installables = if target.expand {
+                let mut eval = Command::new("nix");
+                eval
+                  .args(["eval", "--raw", "--no-write-lock-file"])
+                  .arg(format!("{flake}#{}", target.attr))
+                  .args([
+                    "--apply",
+                    r#"xs: builtins.concatStringsSep "\n" (builtins.attrNames xs) + "\n""#,
+                  ]);
+                nix_env(&mut eval, &input.env);
+                let names = match logger.output_timeout(&mut eval, timeout)? {
+                  Some(names) if names.status.success() => names,
+                  names => {
+                    let kind = eval_failure(&mut logger, names, timeout)?;
+                    fail_outputs(
+                      &finalizer_conn,
+                      input.id,
+                      std::slice::from_ref(&target.system),
+                      kind,
+                    )
+                    .await?;
+                    failures.push(kind);
+                    continue;
+                  }
+                };
+                String::from_utf8_lossy(&names.stdout)
+                  .lines()
+                  .filter(|n| !n.is_empty())
+                  .map(|n| format!("{flake}#{}.\"{n}\"", target.attr))
+                  .collect::<Vec<_>>()
+              } else {
+                vec![format!("{flake}#{}", target.attr)]
+              };
+              if installables.is_empty() {
+                logger.log(format!("nothing to build in {flake}#{}", target.attr))?;
+                sqlx::query!(
+                  "INSERT INTO outputs (input_id, system, status) VALUES ($1, $2, $3)",
+                  input.id,
+                  target.system,
+                  OutputStatus::Skipped as _
+                )
+                .execute(&finalizer_conn)
+                .await?;
+                continue;
+              }
+
+              let eval = nix_eval_command(installables.clone());
+              let build = nix_build_command(installables);
+              plan.push((input.clone(), target.system, eval, build));
+            }
+          } else {
+            for target_system in &systems {
+              let mut eval = Command::new("nix-instantiate");
+              eval.arg(&path).args(["--argstr", "system", target_system]);
+              let mut build = guess_build_command(&path);
+              build
+                .args(["--argstr", "system", target_system])
+                .arg("--keep-going");
+              if let Some(attr) = &input.attr {
+                eval.args(["-A", attr]);
+                build.args(["-A", attr]);
+              }
+              plan.push((input.clone(), target_system.to_string(), eval, build));
+            }
           }
         }
 
-        if !logger
-          .exec(
-            Command::new("git")
-              .args(["worktree", "remove", "--force"])
-              .arg(&build_tag)
-              .current_dir(&scm_dir),
-          )?
-          .success()
-        {
+        let mut queued = vec![];
+        for (input, system, eval, build) in plan {
+          let output_id = sqlx::query_scalar!(
+            "INSERT INTO outputs (input_id, system) VALUES ($1, $2) RETURNING id",
+            input.id,
+            system
+          )
+          .fetch_one(&finalizer_conn)
+          .await?;
+          queued.push((output_id, input, system, eval, build));
+        }
+
+        // a failing pair doesn't stop the rest from being built
+        for (output_id, input, target_system, mut eval, mut build) in queued {
+          let name = match &input.attr {
+            Some(attr) => format!("{} {attr}", input.path),
+            None => input.path.clone(),
+          };
+          macro_rules! fail {
+            ($kind:expr, $msg:expr) => {{
+              logger.log($msg)?;
+              set_output_status(
+                &finalizer_conn,
+                output_id,
+                OutputStatus::Failed,
+                Some($kind),
+              )
+              .await?;
+              failures.push($kind);
+              continue;
+            }};
+          }
+
+          set_output_status(&finalizer_conn, output_id, OutputStatus::Building, None).await?;
+
+          nix_env(&mut eval, &input.env);
+          eval.args(&input.args);
+          let Some(drv_path) = logger.output_timeout(&mut eval, timeout)? else {
+            fail!(
+              FailureKind::TimedOut,
+              format!(
+                "evaluating {name} ({target_system}) timed out after {} seconds",
+                timeout.as_secs()
+              )
+            );
+          };
+          if !drv_path.status.success() {
+            fail!(
+              FailureKind::Evaluation,
+              format!(
+                "evaluating {name} ({target_system}) exited with status {}",
+                drv_path.status
+              )
+            );
+          }
+          let drv_path = String::from_utf8_lossy(&drv_path.stdout)
+            .trim_end()
+            .to_string();
+          sqlx::query!(
+            "UPDATE outputs SET drv_path = $2 WHERE id = $1",
+            output_id,
+            drv_path
+          )
+          .execute(&finalizer_conn)
+          .await?;
+
+          nix_env(&mut build, &input.env);
+          build.args(&input.args);
+          let Some(store_path) = logger.output_timeout(&mut build, timeout)? else {
+            fail!(
+              FailureKind::TimedOut,
+              format!(
+                "{name} ({target_system}) timed out after {} seconds",
+                timeout.as_secs()
+              )
+            );
+          };
+          if !store_path.status.success() {
+            fail!(
+              FailureKind::Error,
+              format!(
+                "building {name} ({target_system}) exited with status {}",
+                store_path.status
+              )
+            );
+          }
+
+          let output_path = String::from_utf8_lossy(&store_path.stdout)
+            .trim_end()
+            .to_string();
+          sqlx::query!(
+            "UPDATE outputs SET store_path = $2 WHERE id = $1",
+            output_id,
+            output_path
+          )
+          .execute(&finalizer_conn)
+          .await?;
+          set_output_status(&finalizer_conn, output_id, OutputStatus::Succeeded, None).await?;
+        }
+
+        if !remove_worktree(&mut logger, &scm_dir, &build_tag)? {
           status!(BuildStatus::Failed, &finalizer_conn);
           return;
         };
 
-        if !logger
-          .exec(
-            Command::new("rm")
-              .args(["-rf"])
-              .arg(scm_dir.join(&build_tag)),
-          )?
-          .success()
-        {
-          status!(BuildStatus::Failed, &finalizer_conn);
+        if let Some(&first) = failures.first() {
+          let kind = if failures.iter().all(|k| *k == first) {
+            first
+          } else {
+            FailureKind::Error
+          };
+          let msg = match kind {
+            FailureKind::TimedOut => format!("{} builds timed out", failures.len()),
+            FailureKind::Evaluation => format!("{} builds didn't evaluate", failures.len()),
+            FailureKind::Error => format!("{} builds failed", failures.len()),
+          };
+          logger.log(&msg)?;
+          set_status(
+            &finalizer_conn,
+            bid,
+            attempt_id,
+            &worker_id,
+            BuildStatus::Failed,
+            Some(kind),
+            Some(&msg),
+          )
+          .await?;
           return;
-        };
+        }
 
         logger.exec(Command::new("echo").arg("Success!"))?;
 
-        sqlx::query!(
-          "UPDATE builds SET status = $2, finished_at = $3 WHERE id = $1",
-          build_info.id,
-          BuildStatus::Succeeded as _,
-          Utc::now()
+        set_status(
+          &finalizer_conn,
+          bid,
+          attempt_id,
+          &worker_id,
+          BuildStatus::Succeeded,
+          None,
+          None,
         )
-        .execute(&finalizer_conn)
         .await?;
       };
       if let Err(e) = build_err {
-        sqlx::query!(
-          "UPDATE builds SET status = $1, error_msg = $2",
-          BuildStatus::Failed as _,
-          format!("{:?}", e)
+        // the worker takes care of canceled builds itself
+        if logger.cancel_handle().is_canceled() {
+          return;
+        }
+        set_status(
+          &finalizer_conn,
+          bid,
+          attempt_id,
+          &worker_id,
+          BuildStatus::Failed,
+          Some(FailureKind::Error),
+          Some(&format!("{:?}", e)),
         )
-        .execute(&finalizer_conn)
         .await
         .expect("unable to update build status, everything is broken");
       }
+    };
+    // the build waits on the commands it runs without yielding, so it gets a
+    // thread of its own. on the runtime's threads, enough builds at once would
+    // starve everything else, the heartbeat included
+    let stopped = Arc::new(Notify::new());
+    let stop = Arc::clone(&stopped);
+    let runtime = tokio::runtime::Handle::current();
+    let jh = tokio::task::spawn_blocking(move || {
+      runtime.block_on(async move {
+        tokio::select! {
+          _ = build => {
+            let _ = finished.send(bid);
+          }
+          // the build might already be running again by the time a stopped
+          // one gets here, so this one doesn't say anything
+          _ = stop.notified() => {}
+        }
+      })
     });
-    self.jobs.insert(bid, jh);
+    self.jobs.insert(
+      bid,
+      Job {
+        handle: jh,
+        cancel,
+        stopped,
+        log_file,
+      },
+    );
     info!("spawned build");
     Ok(())
   }
 
-  fn setup_secrets(upload_config: &Publish, mut build_hook: File) -> Result<()> {
+  // reads and checks a .starfish.toml, along with the arguments for each of its
+  // inputs. `None` if there isn't one
+  #[allow(clippy::type_complexity)]
+  fn load_definition(
+    &self,
+    path: &Path,
+  ) -> Result<Option<(BuildDef, Vec<(Vec<String>, Vec<String>)>)>, String> {
+    let contents = match std::fs::read_to_string(path) {
+      Ok(contents) => contents,
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+      Err(e) => return Err(format!("unable to read .starfish.toml: {e}")),
+    };
+    let definition =
+      toml::from_str::<BuildDef>(&contents).map_err(|e| format!("invalid .starfish.toml: {e}"))?;
+
+    let args = definition
+      .inputs
+      .iter()
+      .map(|i| i.nix_args(&self.cfg.allowed_options, &self.cfg.allowed_env))
+      .collect::<Result<Vec<_>, _>>()
+      .map_err(|e| format!("invalid .starfish.toml: {e}"))?;
+    if definition.timeout_secs.map_or(false, |t| t <= 0) {
+      return Err("invalid .starfish.toml: timeout_secs must be positive".into());
+    }
+    // we already claimed the build, so there's nobody else to hand it to
+    if let Some(system) = definition
+      .systems
+      .iter()
+      .chain(definition.inputs.iter().filter_map(|i| i.systems.as_ref()))
+      .flatten()
+      .find(|s| !self.systems.contains(s))
+    {
+      return Err(format!(
+        "this worker can't build for {system}, which .starfish.toml asks for"
+      ));
+    }
+
+    Ok(Some((definition, args)))
+  }
+
+  // the signing key goes in `dir` next to the hook, so it's around for as long
+  // as the build is
+  fn setup_secrets(upload_config: &Publish, dir: &Path, mut build_hook: File) -> Result<()> {
+    let write_key = |key: &str| -> Result<PathBuf> {
+      let path = dir.join("signing-key");
+      let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&path)?;
+      write!(file, "{key}")?;
+      Ok(path)
+    };
+
     match upload_config {
       Publish::None => scripts::None.write_into(&mut build_hook)?,
       Publish::S3 {
@@ -406,13 +1112,10 @@ impl<'a> Worker<'a> {
         secret_key,
         nix_signing_key,
       } => {
-        let mut signing_key_path = tempfile::NamedTempFile::new()?;
-        write!(signing_key_path, "{nix_signing_key}")?;
-
         let cache_uri = format!(
           "s3://{bucket}?region={region}&secret-key={key_path}&write-nar-listing=1&\
            ls-compression=br&log-compression=br&parallel-compression=1",
-          key_path = signing_key_path.path().display()
+          key_path = write_key(nix_signing_key)?.display()
         );
 
         let post_build_script = scripts::S3 {
@@ -423,11 +1126,206 @@ impl<'a> Worker<'a> {
 
         post_build_script.write_into(&mut build_hook)?;
       }
+      Publish::Local {
+        path,
+        nix_signing_key,
+      } => {
+        let cache_uri = format!(
+          "file://{}?secret-key={}&parallel-compression=1",
+          path.display(),
+          write_key(nix_signing_key)?.display()
+        );
+
+        scripts::Local {
+          cache_uri: &cache_uri,
+        }
+        .write_into(&mut build_hook)?;
+      }
     }
     Ok(())
   }
 }
 
+impl Job {
+  // killing the process group makes the build's current command exit
+  // immediately, and `Cancel` refuses to start any new ones, so this won't wait
+  // long
+  async fn stop(&mut self) {
+    self.cancel.cancel();
+    self.stopped.notify_one();
+    let _ = (&mut self.handle).await;
+  }
+}
+
+// updates both the build and the given attempt at it, as long as that's still
+// the one running. a job that was restarted or taken over elsewhere mustn't
+// touch whatever replaced it
+async fn set_status(
+  db: &PgPool,
+  build_id: i32,
+  attempt_id: i32,
+  worker_id: &str,
+  status: BuildStatus,
+  failure_kind: Option<FailureKind>,
+  error_msg: Option<&str>,
+) -> sqlx::Result<()> {
+  let finished_at = status.is_finished().then(Utc::now);
+  let mut tx = db.begin().await?;
+  // leave the build alone if somebody requeued or canceled it in the meantime
+  let updated = sqlx::query!(
+    "UPDATE builds SET status = $2, finished_at = $3, error_msg = $4, failure_kind = $5 WHERE id \
+     = $1 AND status IN ($6, $7) AND worker_id = $8 AND EXISTS (SELECT 1 FROM build_attempts \
+     WHERE id = $9 AND build_id = $1 AND finished_at IS NULL)",
+    build_id,
+    status as _,
+    finished_at,
+    error_msg,
+    failure_kind as _,
+    BuildStatus::Building as _,
+    BuildStatus::Uploading as _,
+    worker_id,
+    attempt_id
+  )
+  .execute(&mut *tx)
+  .await?;
+  if updated.rows_affected() == 0 {
+    return Ok(());
+  }
+  if status.is_finished() {
+    skip_unfinished_outputs(&mut *tx, &[build_id]).await?;
+  }
+  sqlx::query!(
+    "UPDATE build_attempts SET status = $2, finished_at = $3, error_msg = $4, failure_kind = $5 \
+     WHERE id = $1 AND finished_at IS NULL",
+    attempt_id,
+    status as _,
+    finished_at,
+    error_msg,
+    failure_kind as _
+  )
+  .execute(&mut *tx)
+  .await?;
+  tx.commit().await
+}
+
+async fn set_output_status(
+  db: &PgPool,
+  output_id: i32,
+  status: OutputStatus,
+  failure_kind: Option<FailureKind>,
+) -> sqlx::Result<()> {
+  sqlx::query!(
+    "UPDATE outputs SET status = $2, failure_kind = $3 WHERE id = $1",
+    output_id,
+    status as _,
+    failure_kind as _
+  )
+  .execute(db)
+  .await?;
+  Ok(())
+}
+
+// logs why an evaluation that was run with `output_timeout` didn't work out
+fn eval_failure(
+  logger: &mut Logger,
+  out: Option<Output>,
+  timeout: Duration,
+) -> std::io::Result<FailureKind> {
+  match out {
+    Some(out) => {
+      logger.log(format!("evaluation exited with status {}", out.status))?;
+      Ok(FailureKind::Evaluation)
+    }
+    None => {
+      logger.log(format!(
+        "evaluation timed out after {} seconds",
+        timeout.as_secs()
+      ))?;
+      Ok(FailureKind::TimedOut)
+    }
+  }
+}
+
+// for inputs that fail before we know what's in them
+async fn fail_outputs(
+  db: &PgPool,
+  input_id: i32,
+  systems: &[String],
+  failure_kind: FailureKind,
+) -> sqlx::Result<()> {
+  sqlx::query!(
+    "INSERT INTO outputs (input_id, system, status, failure_kind) SELECT $1, *, $3, $4 FROM \
+     UNNEST($2::text[])",
+    input_id,
+    systems,
+    OutputStatus::Failed as _,
+    failure_kind as _
+  )
+  .execute(db)
+  .await?;
+  Ok(())
+}
+
+// pairs that didn't get to finish won't be built anymore
+async fn skip_unfinished_outputs<'c, E>(executor: E, build_ids: &[i32]) -> sqlx::Result<()>
+where
+  E: Executor<'c, Database = Postgres>,
+{
+  sqlx::query!(
+    "UPDATE outputs o SET status = $2 FROM inputs i WHERE i.id = o.input_id AND i.build_id = \
+     ANY($1) AND o.status IN ($3, $4)",
+    build_ids,
+    OutputStatus::Skipped as _,
+    OutputStatus::Queued as _,
+    OutputStatus::Building as _
+  )
+  .execute(executor)
+  .await?;
+  Ok(())
+}
+
+// keeps our claim on running builds alive. this runs in its own task so that a
+// long checkout in the main loop can't make the lease lapse
+async fn heartbeat(db: PgPool, cfg: Arc<Config>) {
+  let mut interval = tokio::time::interval(Duration::from_secs((cfg.lease_secs / 3).max(1).into()));
+  loop {
+    interval.tick().await;
+    if let Err(e) = sqlx::query!(
+      "UPDATE workers SET last_heartbeat = now() WHERE id = $1",
+      &cfg.worker_id
+    )
+    .execute(&db)
+    .await
+    {
+      warn!("unable to send heartbeat: {:?}", e);
+    }
+  }
+}
+
+fn build_tag(build_id: i32) -> String {
+  format!("__starfish_build_{build_id}")
+}
+
+fn remove_worktree(logger: &mut Logger, scm_dir: &Path, build_tag: &str) -> Result<bool> {
+  Ok(
+    logger
+      .exec(
+        Command::new("git")
+          .args(["worktree", "remove", "--force"])
+          .arg(build_tag)
+          .current_dir(scm_dir),
+      )?
+      .success()
+      && logger
+        .exec(
+          Command::new("rm")
+            .args(["-rf"])
+            .arg(scm_dir.join(build_tag)),
+        )?
+        .success(),
+  )
+}
+
 // best effort to figure out what command will "build" the "thing".
 //
 // nix-shell's job is to realize the contents of environment variables too, but
@@ -445,9 +1343,138 @@ fn guess_build_command(input_path: &str) -> Command {
   }
 }
 
-#[inline]
-fn is_commit_hash(h: &str) -> bool {
-  h.len() == 40 && h.bytes().all(|c| c.is_ascii_hexdigit())
+// paths go first on the command line, so they can't be allowed to look like
+// flags. they also get a `./` so nix takes flakes for directories in the
+// repository, and not for something like `sub/dir` in the registry
+fn path_arg(path: &str) -> String {
+  if !(path.starts_with("./") || path.starts_with('/')) {
+    format!("./{path}")
+  } else {
+    path.to_string()
+  }
+}
+
+// appends an attribute path to an installable or another attribute path
+fn join_attr(parent: &str, attr: &str) -> String {
+  if attr.is_empty() {
+    parent.to_string()
+  } else if parent.is_empty() || parent.ends_with('#') {
+    format!("{parent}{attr}")
+  } else {
+    format!("{parent}.{attr}")
+  }
+}
+
+// walks a jobset the way hydra does, printing `<system> <attr path>` for every
+// derivation in it
+const LIST_JOBS: &str = r#"
+jobs:
+let
+  quote = n: if builtins.match ".*[.\"].*" n == null then n else "\"${n}\"";
+  go = path: v:
+    if builtins.isAttrs v && v.type or null == "derivation" then
+      [ "${v.system} ${builtins.concatStringsSep "." (map quote path)}\n" ]
+    else if builtins.isAttrs v then
+      builtins.concatLists (map (n: go (path ++ [ n ]) v.${n}) (builtins.attrNames v))
+    else
+      [ ];
+in
+builtins.concatStringsSep "" (go [ ] jobs)
+"#;
+
+// prints the .drv paths of the installables without building anything
+fn nix_eval_command<I: IntoIterator<Item = String>>(installables: I) -> Command {
+  let mut cmd = Command::new("nix");
+  cmd
+    .args(["path-info", "--derivation", "--no-write-lock-file"])
+    .args(installables);
+  cmd
+}
+
+fn nix_build_command<I: IntoIterator<Item = String>>(installables: I) -> Command {
+  let mut cmd = Command::new("nix");
+  cmd
+    .args([
+      "build",
+      "--print-out-paths",
+      "--no-link",
+      "--print-build-logs",
+    ])
+    // use the inputs from flake.lock as-is, and don't touch the worktree
+    .arg("--no-write-lock-file")
+    .arg("--keep-going")
+    .args(installables);
+  cmd
+}
+
+// flake outputs that are laid out as `<output>.<system>.<name>`
+const PER_SYSTEM_OUTPUTS: &[&str] = &[
+  "packages",
+  "legacyPackages",
+  "checks",
+  "devShells",
+  "formatter",
+];
+// the ones among those where building everything for a system makes sense
+const EXPANDABLE_OUTPUTS: &[&str] = &["packages", "checks", "devShells"];
+
+// a flake attribute to build for `system`. if `expand` is set, `attr` is an
+// attrset and every derivation directly inside it gets built
+#[derive(Debug, PartialEq, Eq)]
+struct FlakeTarget {
+  system: String,
+  attr: String,
+  expand: bool,
+}
+
+// figures out what to build for the attribute part of a flake input (whatever
+// comes after the `#`). when the attribute path names one of `systems`, that's
+// the only one it gets built for. otherwise it's built once per system in
+// `systems`, resolved the same way `nix build` would for the current system.
+// returns `None` if the system can't be determined
+fn flake_targets(attr: &str, systems: &[String]) -> Option<Vec<FlakeTarget>> {
+  let per_platform = |attr: &dyn Fn(&str) -> String, expand: bool| {
+    systems
+      .iter()
+      .map(|system| FlakeTarget {
+        system: system.clone(),
+        attr: attr(system),
+        expand,
+      })
+      .collect()
+  };
+  let parts = attr.split('.').collect::<Vec<_>>();
+  let is_system = |s: &str| systems.iter().any(|system| system == s);
+  let targets = match parts[..] {
+    [""] => per_platform(&|system| format!("packages.{system}.default"), false),
+    [output] if EXPANDABLE_OUTPUTS.contains(&output) => {
+      per_platform(&|system| format!("{output}.{system}"), true)
+    }
+    [output, system] if EXPANDABLE_OUTPUTS.contains(&output) && is_system(system) => {
+      vec![FlakeTarget {
+        system: system.to_string(),
+        attr: attr.to_string(),
+        expand: true,
+      }]
+    }
+    [output, system, ..] if PER_SYSTEM_OUTPUTS.contains(&output) && is_system(system) => {
+      vec![FlakeTarget {
+        system: system.to_string(),
+        attr: attr.to_string(),
+        expand: false,
+      }]
+    }
+    [name] if !PER_SYSTEM_OUTPUTS.contains(&name) => {
+      per_platform(&|system| format!("packages.{system}.{name}"), false)
+    }
+    // something like `hydraJobs.foo.x86_64-linux`, take a guess
+    _ => vec![FlakeTarget {
+      system: parts.iter().find(|p| is_system(p))?.to_string(),
+      attr: attr.to_string(),
+      expand: false,
+    }],
+  };
+  Some(targets)
 }
 
 #[derive(Template)]
@@ -475,5 +1502,84 @@ async fn main() -> anyhow::Result<()> {
 
   sqlx::migrate!("../migrations").run(&pool).await?;
 
-  Worker::new(cfg, &pool)?.run().await
+  let (worker, finished) = Worker::new(cfg, &pool)?;
+  worker.run(finished).await
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn target(system: &str, attr: &str, expand: bool) -> FlakeTarget {
+    FlakeTarget {
+      system: system.into(),
+      attr: attr.into(),
+      expand,
+    }
+  }
+
+  #[test]
+  fn flake_targets_per_system() {
+    let systems = vec!["x86_64-linux".to_string(), "aarch64-linux".to_string()];
+    assert_eq!(
+      flake_targets("", &systems),
+      Some(vec![
+        target("x86_64-linux", "packages.x86_64-linux.default", false),
+        target("aarch64-linux", "packages.aarch64-linux.default", false),
+      ])
+    );
+    assert_eq!(
+      flake_targets("checks", &systems),
+      Some(vec![
+        target("x86_64-linux", "checks.x86_64-linux", true),
+        target("aarch64-linux", "checks.aarch64-linux", true),
+      ])
+    );
+    assert_eq!(
+      flake_targets("hello", &systems),
+      Some(vec![
+        target("x86_64-linux", "packages.x86_64-linux.hello", false),
+        target("aarch64-linux", "packages.aarch64-linux.hello", false),
+      ])
+    );
+  }
+
+  #[test]
+  fn flake_targets_naming_a_system() {
+    let systems = vec!["x86_64-linux".to_string(), "aarch64-linux".to_string()];
+    assert_eq!(
+      flake_targets("devShells.aarch64-linux", &systems),
+      Some(vec![target(
+        "aarch64-linux",
+        "devShells.aarch64-linux",
+        true
+      )])
+    );
+    assert_eq!(
+      flake_targets("legacyPackages.x86_64-linux.hello", &systems),
+      Some(vec![target(
+        "x86_64-linux",
+        "legacyPackages.x86_64-linux.hello",
+        false
+      )])
+    );
+    assert_eq!(
+      flake_targets("hydraJobs.tests.x86_64-linux", &systems),
+      Some(vec![target(
+        "x86_64-linux",
+        "hydraJobs.tests.x86_64-linux",
+        false
+      )])
+    );
+  }
+
+  #[test]
+  fn flake_targets_without_a_known_system() {
+    let systems = vec!["x86_64-linux".to_string()];
+    // the second part isn't a system, so it isn't taken for one
+    assert_eq!(flake_targets("packages.hello", &systems), None);
+    assert_eq!(flake_targets("checks.aarch64-darwin", &systems), None);
+    assert_eq!(flake_targets("hydraJobs.tests", &systems), None);
+    assert_eq!(flake_targets("formatter", &systems), None);
+  }
 }
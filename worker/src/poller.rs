@@ -0,0 +1,185 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use common::{NewBuild, Project};
+use log::{info, warn};
+use sqlx::PgPool;
+use tokio::process::Command;
+
+use crate::cfg::Config;
+
+// git can hang for a long time on a host that doesn't answer
+const LS_REMOTE_TIMEOUT: Duration = Duration::from_secs(60);
+
+// checks every watched branch for new commits and queues a build for each one
+// that moved. every polling worker does this, the database sorts out which of
+// them gets to queue the build.
+pub async fn poll(db: PgPool, cfg: Arc<Config>, every: Duration) {
+  let mut interval = tokio::time::interval(every);
+  loop {
+    interval.tick().await;
+    if let Err(e) = poll_once(&db, &cfg).await {
+      warn!("unable to poll for new commits: {:?}", e);
+    }
+  }
+}
+
+async fn poll_once(db: &PgPool, cfg: &Config) -> Result<()> {
+  let projects = sqlx::query_as!(
+    Project,
+    "SELECT id, name, origin, default_branch, inputs as \"inputs: _\", systems, publish, \
+     poll_branches, created_at FROM projects WHERE poll_branches <> '{}'"
+  )
+  .fetch_all(db)
+  .await?;
+
+  let targets = projects
+    .iter()
+    .map(|p| (Some(p), &p.origin, &p.poll_branches))
+    .chain(cfg.poll.iter().map(|t| (None, &t.origin, &t.branches)));
+
+  for (project, origin, branches) in targets {
+    let heads = match ls_remote(cfg, origin, branches).await {
+      Ok(heads) => heads,
+      Err(e) => {
+        warn!("unable to list branches of {origin}: {:?}", e);
+        continue;
+      }
+    };
+    for (branch, rev) in heads {
+      if let Err(e) = queue_if_moved(db, cfg, project, origin, &branch, &rev).await {
+        warn!("unable to queue a build of {origin} {branch}: {:?}", e);
+      }
+    }
+  }
+
+  Ok(())
+}
+
+// (branch, commit) for every one of `branches` that exists
+async fn ls_remote(
+  cfg: &Config,
+  origin: &str,
+  branches: &[String],
+) -> Result<Vec<(String, String)>> {
+  let output = tokio::time::timeout(
+    LS_REMOTE_TIMEOUT,
+    Command::new("git")
+      .arg("ls-remote")
+      .arg(origin)
+      .args(branches.iter().map(|b| format!("refs/heads/{b}")))
+      .env("GIT_SSH_COMMAND", cfg.git_ssh_command())
+      .env("GIT_TERMINAL_PROMPT", "0")
+      .kill_on_drop(true)
+      .output(),
+  )
+  .await
+  .context("git ls-remote timed out")??;
+
+  if !output.status.success() {
+    bail!(
+      "git ls-remote failed: {}",
+      String::from_utf8_lossy(&output.stderr).trim_end()
+    );
+  }
+
+  Ok(
+    String::from_utf8_lossy(&output.stdout)
+      .lines()
+      .filter_map(|line| {
+        let (rev, name) = line.split_once('\t')?;
+        let branch = name.strip_prefix("refs/heads/")?;
+        // ls-remote matches patterns by their tail, so `main` also finds
+        // `refs/heads/feature/main`
+        branches
+          .iter()
+          .any(|b| b == branch)
+          .then(|| (branch.to_string(), rev.to_string()))
+      })
+      .collect(),
+  )
+}
+
+async fn queue_if_moved(
+  db: &PgPool,
+  cfg: &Config,
+  project: Option<&Project>,
+  origin: &str,
+  branch: &str,
+  rev: &str,
+) -> Result<()> {
+  let mut tx = db.begin().await?;
+
+  // the first worker to record the new head is the one that queues the build,
+  // everyone else finds it already recorded (after waiting for the row lock)
+  let moved = sqlx::query_scalar!(
+    "INSERT INTO branch_heads (origin, branch, rev) VALUES ($1, $2, $3) ON CONFLICT (origin, \
+     branch) DO UPDATE SET rev = excluded.rev, updated_at = now() WHERE branch_heads.rev <> \
+     excluded.rev RETURNING true as \"moved!\"",
+    origin,
+    branch,
+    rev
+  )
+  .fetch_optional(&mut *tx)
+  .await?
+  .is_some();
+  if !moved {
+    return Ok(());
+  }
+
+  // someone already asked for this exact commit
+  let exists = sqlx::query_scalar!(
+    "SELECT EXISTS (SELECT 1 FROM builds WHERE origin = $1 AND rev = $2) as \"exists!\"",
+    origin,
+    rev
+  )
+  .fetch_one(&mut *tx)
+  .await?;
+  if exists {
+    tx.commit().await?;
+    return Ok(());
+  }
+
+  let inputs = match project {
+    Some(project) => project
+      .inputs
+      .iter()
+      .map(|i| {
+        i.nix_args(&cfg.allowed_options, &cfg.allowed_env)
+          .map(|(args, env)| (i, args, env))
+      })
+      .collect::<Result<Vec<_>, _>>(),
+    None => Ok(vec![]),
+  };
+  let inputs = match inputs {
+    Ok(inputs) => inputs,
+    Err(msg) => {
+      // the head is still recorded, so this isn't retried until the next commit
+      tx.commit().await?;
+      bail!(msg);
+    }
+  };
+
+  let build = NewBuild {
+    origin,
+    rev,
+    branch: Some(branch),
+    priority: 0,
+    timeout_secs: None,
+    systems: project.and_then(|p| p.systems.as_deref()),
+    project_id: project.map(|p| p.id),
+    publish: project.and_then(|p| p.publish.as_deref()),
+    submitted_by: None,
+    inputs,
+  }
+  .enqueue(&mut tx)
+  .await?;
+  tx.commit().await?;
+
+  info!(
+    "{origin} {branch} is now at {rev}, queued build {}",
+    build.id
+  );
+  Ok(())
+}
@@ -8,6 +8,12 @@ pub struct S3<'a> {
   pub cache_uri: &'a str,
 }
 
+#[derive(Template)]
+#[template(path = "post-build/local.sh", escape = "none")]
+pub struct Local<'a> {
+  pub cache_uri: &'a str,
+}
+
 #[derive(Template)]
 #[template(path = "post-build/none.sh", escape = "none")]
 pub struct None;
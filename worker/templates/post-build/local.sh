@@ -0,0 +1,8 @@
+#!/bin/sh
+
+set -eu
+set -f
+export IFS=' '
+
+echo "Copying paths to the binary cache" $OUT_PATHS
+exec /nix/var/nix/profiles/default/bin/nix copy -v --to '{{ cache_uri }}' $OUT_PATHS